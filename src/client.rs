@@ -0,0 +1,210 @@
+use std::sync::Arc;
+use url::Url;
+
+use crate::error::{api_error, Context, Error, Result};
+use crate::oauth::OAuthCredentials;
+use crate::rate_limit::RateLimit;
+use crate::retry::RetryPolicy;
+use crate::types::{parse_posts, ErrorEnvelope, Post, ReturnVal};
+
+/// Where API requests go, unless overridden.
+pub const API_HOST: &str = "https://api.tumblr.com";
+
+/// How many posts the API sends per page, at most.
+pub const PAGE_SIZE: u32 = 20;
+
+/// Checks that a host is a base URL, like `http://localhost:8080`.
+pub fn check_host(host: &str) -> Result<()> {
+    match Url::parse(host) {
+        Ok(url) if ["http", "https"].contains(&url.scheme()) && url.has_host() => Ok(()),
+        _ => Err(Error::Config(format!(
+            "\"{}\" is not a base URL like http://localhost:8080",
+            host
+        ))),
+    }
+}
+
+/// Whose likes to fetch.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Likes {
+    /// A blog that shares its likes publicly.
+    Blog(String),
+    /// The signed in user's own likes, which needs OAuth credentials.
+    User,
+}
+
+impl Likes {
+    /// Tells these likes apart from others, e.g. in the sync state.
+    pub fn key(&self) -> &str {
+        match self {
+            Likes::Blog(name) => name,
+            // Not a valid blog name, so it can't clash with one
+            Likes::User => "@user",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ClientOptions {
+    pub api_key: String,
+    /// Signs requests, needed for the user's own likes.
+    pub oauth: Option<OAuthCredentials>,
+    /// A base URL like `https://api.tumblr.com`, without a trailing slash.
+    pub api_host: String,
+    /// Fetches media on Tumblr's servers from this base URL instead.
+    pub media_host: Option<String>,
+    /// Asks for posts in the Neue Post Format.
+    pub npf: bool,
+    pub retry: RetryPolicy,
+}
+
+impl ClientOptions {
+    pub fn new(api_key: &str) -> ClientOptions {
+        ClientOptions {
+            api_key: api_key.to_string(),
+            oauth: None,
+            api_host: API_HOST.to_string(),
+            media_host: None,
+            npf: false,
+            retry: RetryPolicy::default(),
+        }
+    }
+}
+
+/// A page of liked posts.
+#[derive(Debug)]
+pub struct Page {
+    /// How many posts have been liked in total.
+    pub liked_count: i32,
+    pub posts: Vec<Post>,
+    /// Why each post on the page that could not be read was left out.
+    pub skipped: Vec<String>,
+    /// The cursor for the next page, if there is one.
+    pub next: Option<String>,
+    pub rate_limit: RateLimit,
+}
+
+/// Talks to Tumblr's API and media servers. Cheap to clone.
+#[derive(Debug, Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    options: Arc<ClientOptions>,
+}
+
+impl Client {
+    /// Fails with `Error::Config` if the API or media host isn't a base URL.
+    pub fn new(options: ClientOptions) -> Result<Client> {
+        for host in std::iter::once(&options.api_host).chain(&options.media_host) {
+            check_host(host)?;
+        }
+
+        Ok(Client {
+            http: reqwest::Client::new(),
+            options: Arc::new(options),
+        })
+    }
+
+    pub fn http(&self) -> &reqwest::Client {
+        &self.http
+    }
+
+    pub fn options(&self) -> &ClientOptions {
+        &self.options
+    }
+
+    /// The URL of a page of likes, starting before the `before` cursor.
+    pub fn likes_url(&self, likes: &Likes, limit: u32, before: Option<&str>) -> String {
+        let options = &self.options;
+
+        let mut before = match before {
+            Some(b) => format!("&before={}", b),
+            _ => "".to_string(),
+        };
+
+        if options.npf {
+            before.push_str("&npf=true");
+        }
+
+        match likes {
+            // Signed requests get the authenticated user's own likes
+            Likes::User => format!(
+                "{}/v2/user/likes?limit={}{}",
+                options.api_host, limit, before
+            ),
+            Likes::Blog(name) => format!(
+                "{}/v2/blog/{}/likes?api_key={}&limit={}{}",
+                options.api_host, name, options.api_key, limit, before
+            ),
+        }
+    }
+
+    /// Starts a GET request to the API, signed if we have OAuth credentials.
+    pub fn api_get(&self, url: &str) -> reqwest::RequestBuilder {
+        match &self.options.oauth {
+            Some(oauth) => oauth.get(&self.http, url),
+            None => self.http.get(url),
+        }
+    }
+
+    /// Fetches up to `limit` likes, starting before the `before` cursor.
+    pub async fn likes_page(
+        &self,
+        likes: &Likes,
+        limit: u32,
+        before: Option<&str>,
+    ) -> Result<Page> {
+        let url = self.likes_url(likes, limit, before);
+
+        let res = self
+            .options
+            .retry
+            .send(|| self.api_get(&url))
+            .await
+            .with_context(|| "Could not reach Tumblr")?;
+
+        let rate_limit = RateLimit::from_headers(res.headers());
+        let response = read_likes(res, likes).await?.response;
+        let (posts, skipped) = parse_posts(response.liked_posts);
+
+        let next = match likes {
+            // The user endpoint pages by when posts were liked
+            Likes::User => posts
+                .last()
+                .and_then(|p| p.liked_timestamp)
+                .map(|t| t.to_string()),
+            Likes::Blog(_) => response
+                ._links
+                .and_then(|l| l.next)
+                .map(|n| n.query_params.before),
+        };
+
+        Ok(Page {
+            liked_count: response.liked_count,
+            posts,
+            skipped,
+            next,
+            rate_limit,
+        })
+    }
+}
+
+/// Reads a page of likes, or explains why the API didn't send one.
+async fn read_likes(res: reqwest::Response, likes: &Likes) -> Result<ReturnVal> {
+    let status = res.status();
+    let body = res
+        .text()
+        .await
+        .with_context(|| "Could not read Tumblr's response")?;
+
+    if !status.is_success() {
+        let envelope: Option<ErrorEnvelope> = serde_json::from_str(&body).ok();
+        let blog = match likes {
+            Likes::Blog(name) => name,
+            Likes::User => "",
+        };
+
+        return Err(api_error(status.as_u16(), envelope, blog));
+    }
+
+    serde_json::from_str(&body).with_context(|| "Could not understand Tumblr's response")
+}
@@ -0,0 +1,684 @@
+use futures::future::join_all;
+use indicatif::ProgressBar;
+use reqwest::header::{CONTENT_RANGE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE};
+use reqwest::StatusCode;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Semaphore;
+use tokio::task::JoinHandle;
+use url::{Position, Url};
+
+use crate::client::Client;
+use crate::error::{Context, Error, Result};
+use crate::manifest::Manifest;
+use crate::npf;
+use crate::photo_size::PhotoSize;
+use crate::retry::RetryPolicy;
+use crate::storage::{LocalStorage, Storage};
+use crate::template::{Template, DEFAULT_TEMPLATE};
+use crate::types::{AudioPost, Post, PostKind};
+use crate::util::{inline_media, warn};
+
+#[derive(Debug, Clone)]
+pub struct DownloadOptions {
+    /// Where files are saved, along with the manifest.
+    pub directory: String,
+    /// How files are named, relative to `directory`.
+    pub template: Template,
+    pub photo_size: PhotoSize,
+    /// Fetches photos at 2048 pixels wide where Tumblr has them.
+    pub upscale: bool,
+    /// How many files are downloaded at once.
+    pub jobs: usize,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> DownloadOptions {
+        DownloadOptions {
+            directory: "downloads".to_string(),
+            template: DEFAULT_TEMPLATE
+                .parse()
+                .expect("the default template is valid"),
+            photo_size: PhotoSize::Largest,
+            upscale: false,
+            jobs: 4,
+        }
+    }
+}
+
+/// A file downloaded into storage.
+#[derive(Debug)]
+pub struct SavedFile {
+    pub url: String,
+    /// Where it is kept, relative to the storage's root.
+    pub key: String,
+    pub size: u64,
+}
+
+/// The files downloaded for a single post.
+#[derive(Debug)]
+pub struct PostFiles {
+    pub id: u64,
+    pub blog_name: String,
+    pub files: Vec<SavedFile>,
+    /// The URL of each file that couldn't be downloaded or saved, along
+    /// with why. Track details go by the URL from [`track_details_url`].
+    pub failed: Vec<(String, Error)>,
+}
+
+impl PostFiles {
+    /// Whether every file was downloaded, or had been already.
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+pub type PostDownload = JoinHandle<PostFiles>;
+
+/// Downloads the media of liked posts, keeping track of it in the manifest.
+/// Cheap to clone, and clones share the manifest.
+#[derive(Debug, Clone)]
+pub struct Downloader {
+    client: Client,
+    options: Arc<DownloadOptions>,
+    manifest: Arc<Mutex<Manifest>>,
+    storage: Arc<dyn Storage>,
+    limit: Arc<Semaphore>,
+}
+
+impl Downloader {
+    /// Saves files into the download directory, unless told to use other
+    /// storage with [`Downloader::storing_in`].
+    pub fn new(client: &Client, options: DownloadOptions, manifest: Manifest) -> Downloader {
+        Downloader {
+            client: client.clone(),
+            limit: Arc::new(Semaphore::new(options.jobs)),
+            storage: Arc::new(LocalStorage::new(&options.directory)),
+            options: Arc::new(options),
+            manifest: Arc::new(Mutex::new(manifest)),
+        }
+    }
+
+    /// Saves files into `storage` instead. The download directory still holds
+    /// the manifest, and files while they are being downloaded.
+    pub fn storing_in(mut self, storage: Arc<dyn Storage>) -> Downloader {
+        self.storage = storage;
+        self
+    }
+
+    pub fn storage(&self) -> &dyn Storage {
+        self.storage.as_ref()
+    }
+
+    pub fn options(&self) -> &DownloadOptions {
+        &self.options
+    }
+
+    /// The manifest, locked. Don't hold it across an `await`.
+    pub fn manifest(&self) -> MutexGuard<'_, Manifest> {
+        // Nothing panics while holding it, and a record is pushed in one go
+        self.manifest.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Spawns a download task for each post. At most `jobs` files are fetched
+    /// at once, across all posts. The returned handles are in the same order as `posts`.
+    pub fn download_posts(&self, posts: Vec<Post>, bar: &ProgressBar) -> Vec<PostDownload> {
+        posts
+            .into_iter()
+            .map(|post| self.download_post(post, bar))
+            .collect()
+    }
+
+    /// Spawns a task downloading every file of a post.
+    pub fn download_post(&self, post: Post, bar: &ProgressBar) -> PostDownload {
+        let media = post_media(&post, &self.options.photo_size);
+
+        let media: Vec<_> = media
+            .into_iter()
+            .enumerate()
+            .map(|(i, (folder, url))| (self.options.template.render(&post, folder, i, &url), url))
+            .collect();
+
+        // Keep the track details next to the audio file, which always comes first
+        let track_details = match (&post.kind, media.first()) {
+            (PostKind::Audio(p), Some((audio, url))) if p.tumblr_audio_url().is_some() => Some((
+                Path::new(audio)
+                    .with_extension("json")
+                    .to_string_lossy()
+                    .to_string(),
+                track_details_url(url),
+                audio_metadata(&post.post_url, p),
+            )),
+            _ => None,
+        };
+
+        let (id, blog_name) = (post.id, post.blog_name);
+        let downloader = self.clone();
+        let bar = bar.clone();
+
+        tokio::spawn(async move {
+            let downloads = media.into_iter().map(|(file, url)| {
+                let (downloader, bar) = (&downloader, &bar);
+
+                async move {
+                    // The semaphore is never closed
+                    let _permit = downloader.limit.acquire().await.ok();
+
+                    match downloader.download(id, &file, url.clone()).await {
+                        Ok(size) => Ok(size.map(|size| SavedFile {
+                            url,
+                            key: file,
+                            size,
+                        })),
+                        Err(e) => {
+                            warn(bar, e.to_string());
+                            Err((url, e))
+                        }
+                    }
+                }
+            });
+
+            let results = join_all(downloads).await;
+
+            // Written whenever they're missing, as long as the audio is there
+            let track_details = match results.first() {
+                Some(Ok(_)) => track_details,
+                _ => None,
+            };
+
+            let mut files = Vec::new();
+            let mut failed = Vec::new();
+
+            for result in results {
+                match result {
+                    Ok(Some(file)) => files.push(file),
+                    Ok(None) => {}
+                    Err(failure) => failed.push(failure),
+                }
+            }
+
+            if let Some((key, url, contents)) = track_details {
+                match downloader.save(id, &key, &url, contents).await {
+                    Ok(Some(size)) => files.push(SavedFile { url, key, size }),
+                    Ok(None) => {}
+                    Err(e) => {
+                        warn(&bar, e.to_string());
+                        failed.push((url, e));
+                    }
+                }
+            }
+
+            // Recorded as each post finishes, so clones of the downloader
+            // see every file, and nothing is lost if a later post fails
+            let mut manifest = downloader.manifest();
+
+            for file in &files {
+                manifest.record(id, &blog_name, &file.url, Path::new(&file.key), file.size);
+            }
+
+            drop(manifest);
+            bar.inc(1);
+
+            PostFiles {
+                id,
+                blog_name,
+                files,
+                failed,
+            }
+        })
+    }
+
+    /// Waits for downloads, in the order the posts were liked, and saves the
+    /// manifest. Returns each post's files, in the same order as `pending`,
+    /// leaving out posts whose task failed outright.
+    pub async fn finish(
+        self,
+        pending: Vec<PostDownload>,
+        bar: &ProgressBar,
+    ) -> Result<Vec<PostFiles>> {
+        let mut posts = Vec::new();
+
+        for post in pending {
+            match post.await {
+                Ok(post) => posts.push(post),
+                Err(e) => warn(bar, format!("A download task failed: {}", e)),
+            }
+        }
+
+        if posts.iter().any(|post| !post.files.is_empty()) {
+            self.manifest()
+                .save()
+                .with_context(|| "Could not save the manifest")?;
+        }
+
+        Ok(posts)
+    }
+
+    /// Downloads one of post `id`'s files into storage as `key`, and returns
+    /// its size. Returns `None` if it was already downloaded.
+    async fn download(&self, id: u64, key: &str, url: String) -> Result<Option<u64>> {
+        // Skip files the manifest says are already downloaded
+        let archived = self.manifest().archived(id, &url).cloned();
+
+        if let Some(media) = archived {
+            if media.is_stored(self.storage()).await {
+                return Ok(None);
+            }
+        }
+
+        // Still recorded under the original URL, so it is skipped next time
+        let source = self.media_url(&url);
+        let source = if self.options.upscale {
+            upscale(self.client.http(), self.retry(), &source).await
+        } else {
+            source
+        };
+
+        download_url(
+            self.client.http(),
+            self.retry(),
+            &source,
+            self.storage(),
+            key,
+            Path::new(&self.options.directory),
+        )
+        .await
+        .map(Some)
+    }
+
+    /// Saves `contents` into storage as `key`, by way of the download
+    /// directory, unless it's already there. Like [`Downloader::download`],
+    /// returns its size, or `None` if the manifest already has it as `url`.
+    async fn save(&self, id: u64, key: &str, url: &str, contents: String) -> Result<Option<u64>> {
+        let archived = self.manifest().archived(id, url).cloned();
+
+        if let Some(media) = archived {
+            if media.is_stored(self.storage()).await {
+                return Ok(None);
+            }
+        }
+
+        if let Some(size) = self.storage.stat(key).await? {
+            return Ok(Some(size));
+        }
+
+        let part = part_path(Path::new(&self.options.directory), key);
+
+        if let Some(parent) = part.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Could not create directory {}", parent.display()))?;
+        }
+
+        fs::write(&part, &contents)
+            .with_context(|| format!("Could not write {}", part.display()))?;
+        self.storage.put(key, &part).await?;
+
+        Ok(Some(contents.len() as u64))
+    }
+
+    /// Fetches one of post `id`'s files into `directory`, reusing the archived
+    /// copy if the manifest has one.
+    pub(crate) async fn fetch_into(
+        &self,
+        directory: &str,
+        id: u64,
+        url: String,
+    ) -> Result<PathBuf> {
+        let archived = self.manifest().local_path(id, &url);
+
+        if let Some(path) = archived {
+            return Ok(path);
+        }
+
+        let filename = url.rsplit('/').next().unwrap_or_default();
+        let storage = LocalStorage::new(directory);
+
+        download_url(
+            self.client.http(),
+            self.retry(),
+            &self.media_url(&url),
+            &storage,
+            filename,
+            Path::new(directory),
+        )
+        .await?;
+
+        Ok(storage.path(filename))
+    }
+
+    fn retry(&self) -> &RetryPolicy {
+        &self.client.options().retry
+    }
+
+    /// Sends media on Tumblr's servers to the media host override, if there is one.
+    fn media_url(&self, url: &str) -> String {
+        let host = match &self.client.options().media_host {
+            Some(host) => host,
+            None => return url.to_string(),
+        };
+
+        match Url::parse(url) {
+            Ok(parsed)
+                if parsed
+                    .host_str()
+                    .is_some_and(|h| h == "tumblr.com" || h.ends_with(".tumblr.com")) =>
+            {
+                format!("{}{}", host, &parsed[Position::BeforePath..])
+            }
+            _ => url.to_string(),
+        }
+    }
+}
+
+/// Every file of a post, along with the folder each belongs in. An audio
+/// post's track always comes first.
+pub(crate) fn post_media(post: &Post, photo_size: &PhotoSize) -> Vec<(&'static str, String)> {
+    let mut media: Vec<(&'static str, String)> = Vec::new();
+
+    match &post.kind {
+        PostKind::Photo(p) => {
+            for url in p.photos.iter().filter_map(|photo| photo.url(photo_size)) {
+                media.push(("pics", url.to_string()));
+            }
+        }
+        PostKind::Video(p) => {
+            if let Some(url) = &p.video_url {
+                media.push(("videos", url.clone()));
+            }
+        }
+        PostKind::Audio(p) => {
+            if let Some(url) = p.tumblr_audio_url() {
+                media.push(("audio", url.clone()));
+            }
+
+            if let Some(url) = &p.album_art {
+                media.push(("audio", url.clone()));
+            }
+        }
+        // These only have media embedded in their text
+        PostKind::Text(_)
+        | PostKind::Quote(_)
+        | PostKind::Link(_)
+        | PostKind::Chat(_)
+        | PostKind::Answer(_)
+        | PostKind::Blocks
+        | PostKind::Unknown => {}
+    }
+
+    // Media embedded in the text of any kind of post, or in its content
+    // blocks for Neue Post Format posts
+    for (folder, url) in inline_media(post)
+        .into_iter()
+        .chain(npf::post_media(post, photo_size))
+    {
+        if !media.iter().any(|(_, u)| *u == url) {
+            media.push((folder, url));
+        }
+    }
+
+    media
+}
+
+const PART_EXTENSION: &str = ".part";
+
+/// Kept next to a partial download, holding what identifies the version of
+/// the file being downloaded.
+const VALIDATOR_EXTENSION: &str = ".part.validator";
+
+/// Where `key` is downloaded to in `directory`, before it is stored.
+fn part_path(directory: &Path, key: &str) -> PathBuf {
+    directory.join(format!("{}{}", key, PART_EXTENSION))
+}
+
+fn validator_path(directory: &Path, key: &str) -> PathBuf {
+    directory.join(format!("{}{}", key, VALIDATOR_EXTENSION))
+}
+
+/// What a later request can send as `If-Range`, to only resume the same
+/// version of the file: a strong ETag, or else when it was last modified.
+fn validator(res: &reqwest::Response) -> Option<String> {
+    let header = |name| res.headers().get(name)?.to_str().ok();
+
+    header(ETAG)
+        .filter(|etag| !etag.starts_with("W/"))
+        .or_else(|| header(LAST_MODIFIED))
+        .map(str::to_string)
+}
+
+/// The first byte and total length from a `Content-Range` header like
+/// `bytes 100-199/200`. The total is `None` if the server doesn't know it.
+fn content_range(res: &reqwest::Response) -> Option<(u64, Option<u64>)> {
+    let range = res.headers().get(CONTENT_RANGE)?.to_str().ok()?;
+    let (range, total) = range.strip_prefix("bytes ")?.split_once('/')?;
+    let (start, _) = range.split_once('-')?;
+
+    Some((start.parse().ok()?, total.parse().ok()))
+}
+
+/// Downloads `url` into `storage` as `key`, and returns its size. The file is
+/// streamed into `directory` first, resuming a previous attempt if one was cut
+/// short and the file hasn't changed since. Downloads cut short are tried again
+/// the same way, as often as `retry` allows. Fails if the server doesn't have
+/// the file, or sent more than it said it would.
+pub(crate) async fn download_url(
+    client: &reqwest::Client,
+    retry: &RetryPolicy,
+    url: &str,
+    storage: &dyn Storage,
+    key: &str,
+    directory: &Path,
+) -> Result<u64> {
+    // Skip existing files
+    if let Some(size) = storage.stat(key).await? {
+        return Ok(size);
+    }
+
+    // Stream into a temporary file, so an interrupted download is never
+    // mistaken for a complete one
+    let part = part_path(directory, key);
+
+    if let Some(parent) = part.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Could not create directory {}", parent.display()))?;
+    }
+
+    let mut attempt = 1;
+
+    let written = loop {
+        match fetch_part(client, retry, url, &part, &validator_path(directory, key)).await? {
+            Fetched::Complete(written) => break written,
+            Fetched::CutShort(_) if attempt < retry.max_attempts => {
+                tokio::time::sleep(retry.backoff(attempt)).await;
+                attempt += 1;
+            }
+            Fetched::CutShort(e) => return Err(e),
+        }
+    };
+
+    storage.put(key, &part).await?;
+
+    Ok(written)
+}
+
+/// How far an attempt at downloading a file got.
+enum Fetched {
+    /// The whole file is in the part file, this many bytes of it.
+    Complete(u64),
+    /// The body stopped early. What arrived is kept, to resume from.
+    CutShort(Error),
+}
+
+/// Makes one attempt at downloading `url` into `part`, picking up where a
+/// previous attempt stopped if `validator_file` says which version it was.
+async fn fetch_part(
+    client: &reqwest::Client,
+    retry: &RetryPolicy,
+    url: &str,
+    part: &Path,
+    validator_file: &Path,
+) -> Result<Fetched> {
+    // Pick up where a previous, interrupted download stopped, as long as we
+    // can tell the server which version of the file that was
+    let previous = fs::read_to_string(validator_file).ok();
+    let resume_from = match &previous {
+        Some(_) => fs::metadata(part).map(|m| m.len()).unwrap_or(0),
+        None => 0,
+    };
+
+    let mut res = retry
+        .send(|| match &previous {
+            Some(previous) if resume_from > 0 => client
+                .get(url)
+                .header(RANGE, format!("bytes={}-", resume_from))
+                .header(IF_RANGE, previous),
+            _ => client.get(url),
+        })
+        .await
+        .with_context(|| format!("Could not download {}", url))?;
+
+    // Where the body goes in the file. Usable if that's the start, or where
+    // the part file ends.
+    let offset = |res: &reqwest::Response| match res.status() {
+        StatusCode::PARTIAL_CONTENT => content_range(res)
+            .map(|(start, _)| start)
+            .filter(|&start| start == 0 || start == resume_from),
+        _ => Some(0),
+    };
+
+    // The rest of the file doesn't line up with what we have, start over
+    if res.status() == StatusCode::RANGE_NOT_SATISFIABLE || offset(&res).is_none() {
+        res = retry
+            .send(|| client.get(url))
+            .await
+            .with_context(|| format!("Could not download {}", url))?;
+    }
+
+    let mut res = res
+        .error_for_status()
+        .with_context(|| format!("Could not download {}", url))?;
+
+    let start = offset(&res).ok_or_else(|| {
+        Error::Transfer(format!(
+            "Could not download {}: the server sent a part of the file that wasn't asked for",
+            url
+        ))
+    })?;
+
+    // Servers send the whole file instead if it changed, or they can't send ranges
+    let resumed = start > 0;
+    let mut written = start;
+    let expected = match content_range(&res) {
+        Some((_, total)) if res.status() == StatusCode::PARTIAL_CONTENT => total,
+        _ => res.content_length().map(|len| len + written),
+    };
+
+    let f = if resumed {
+        OpenOptions::new().append(true).open(part).await
+    } else {
+        // Remember which version this is, in case it gets cut short
+        match validator(&res) {
+            Some(validator) => fs::write(validator_file, validator),
+            None => fs::remove_file(validator_file).or_else(|e| match e.kind() {
+                io::ErrorKind::NotFound => Ok(()),
+                _ => Err(e),
+            }),
+        }
+        .with_context(|| format!("Could not write {}", validator_file.display()))?;
+
+        File::create(part).await
+    };
+    let mut f = f.with_context(|| format!("Could not create {}", part.display()))?;
+
+    let cut_short = loop {
+        let chunk = match res.chunk().await {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => break None,
+            Err(e) => break Some(e),
+        };
+
+        f.write_all(&chunk)
+            .await
+            .with_context(|| format!("Could not write to {}", part.display()))?;
+        written += chunk.len() as u64;
+    };
+
+    f.flush()
+        .await
+        .with_context(|| format!("Could not write to {}", part.display()))?;
+    drop(f);
+
+    // Keep what we have, the next attempt resumes from here
+    if let Some(source) = cut_short {
+        return Ok(Fetched::CutShort(Error::Http {
+            context: format!("Could not download {}", url),
+            source,
+        }));
+    }
+
+    match expected {
+        Some(len) if written < len => {
+            return Ok(Fetched::CutShort(Error::Transfer(format!(
+                "Could not download {}: the server only sent {} of {} bytes",
+                url, written, len
+            ))));
+        }
+        Some(len) if written > len => {
+            fs::remove_file(part)
+                .with_context(|| format!("Could not remove {}", part.display()))?;
+            return Err(Error::Transfer(format!(
+                "Could not download {}: the server sent {} bytes instead of {}",
+                url, written, len
+            )));
+        }
+        _ => {}
+    }
+
+    // Nothing is left to resume
+    fs::remove_file(validator_file).ok();
+
+    Ok(Fetched::Complete(written))
+}
+
+/// Photo URLs name their size, and the API never lists sizes above 1280 pixels
+/// wide. Swaps in 2048 pixels instead, if the server has that size.
+async fn upscale(client: &reqwest::Client, retry: &RetryPolicy, url: &str) -> String {
+    for (from, to) in [("_1280.", "_2048."), ("/s1280x1920/", "/s2048x3072/")].iter() {
+        if !url.contains(from) {
+            continue;
+        }
+
+        let larger = url.replacen(from, to, 1);
+
+        if let Ok(res) = retry.send(|| client.head(&larger)).await {
+            if res.status().is_success() {
+                return larger;
+            }
+        }
+    }
+
+    url.to_string()
+}
+
+/// What the track details of the audio file at `audio_url` are recorded as in
+/// the manifest, since they have no URL of their own.
+pub fn track_details_url(audio_url: &str) -> String {
+    format!("{}#track-details", audio_url)
+}
+
+/// The track details of an audio post, as JSON.
+fn audio_metadata(post_url: &str, audio: &AudioPost) -> String {
+    let metadata = serde_json::json!({
+        "track_name": audio.track_name,
+        "artist": audio.artist,
+        "album": audio.album,
+        "post_url": post_url,
+        "audio_url": audio.audio_url,
+        "album_art": audio.album_art,
+    });
+
+    format!("{:#}", metadata)
+}
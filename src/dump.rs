@@ -0,0 +1,21 @@
+use std::fs::{self, File};
+use std::io::BufReader;
+
+use crate::error::{Context, Result};
+use crate::types::{parse_posts, Post};
+
+/// Saves liked posts as JSON, to be restored later.
+pub fn save(posts: &[Post], file: &str) -> Result<()> {
+    let json = serde_json::to_string(posts).with_context(|| "Couldn't serialize liked posts")?;
+    fs::write(file, json).with_context(|| format!("Couldn't write to {}", file))
+}
+
+/// Reads liked posts saved with [`save`], skipping any that can't be read.
+/// Returns the posts, and a message for each one skipped.
+pub fn load(file: &str) -> Result<(Vec<Post>, Vec<String>)> {
+    let f = File::open(file).with_context(|| format!("Couldn't open {}", file))?;
+
+    let res: Vec<serde_json::Value> = serde_json::from_reader(BufReader::new(f))
+        .with_context(|| format!("Couldn't read the dump {}", file))?;
+    Ok(parse_posts(res))
+}
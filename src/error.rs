@@ -0,0 +1,163 @@
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+use crate::rate_limit::minutes;
+use crate::types::ErrorEnvelope;
+
+/// Everything that can go wrong during a run.
+#[derive(Debug)]
+pub enum Error {
+    /// Tumblr answered, but with an error.
+    Api(String),
+    /// The day's API requests are used up. Paging can carry on from the
+    /// `resume` cursor once they reset.
+    DailyLimit {
+        resume: String,
+        reset: Duration,
+    },
+    /// A request could not be sent, or its response could not be read.
+    Http {
+        context: String,
+        source: reqwest::Error,
+    },
+    /// A request went through, but what came back wasn't what was promised,
+    /// like a download cut short or a sign in reply missing its token.
+    Transfer(String),
+    Io {
+        context: String,
+        source: io::Error,
+    },
+    /// A dump, page of likes or other JSON document could not be read or written.
+    Parse {
+        context: String,
+        source: serde_json::Error,
+    },
+    /// The command line doesn't add up.
+    Config(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// The process exit code for this kind of error. 1 is left for panics and
+    /// 2 matches the one for invalid command line arguments.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Config(_) => 2,
+            Error::Api(_) | Error::DailyLimit { .. } => 3,
+            Error::Http { .. } | Error::Transfer(_) => 4,
+            Error::Io { .. } => 5,
+            Error::Parse { .. } => 6,
+        }
+    }
+}
+
+/// Explains a failed API request, with what to do about it. `blog` is empty
+/// when downloading the signed in user's likes.
+pub fn api_error(status: u16, envelope: Option<ErrorEnvelope>, blog: &str) -> Error {
+    let (status, msg, errors) = match envelope {
+        Some(e) => (e.meta.status, e.meta.msg, e.errors),
+        None => (status, String::new(), Vec::new()),
+    };
+
+    let details: Vec<String> = errors
+        .into_iter()
+        .filter_map(|e| e.detail.or(e.title))
+        .collect();
+    let details = if details.is_empty() {
+        String::new()
+    } else {
+        format!(" Tumblr says: {}", details.join(" "))
+    };
+
+    let message = match status {
+        401 => "Tumblr didn't accept your credentials. Make sure your API key is right, \
+             and if you're signed in, that your token and token secret are too."
+            .to_string(),
+        403 if blog.is_empty() => "Tumblr won't share your likes. Try signing in again \
+             with --authorize."
+            .to_string(),
+        403 => format!(
+            "{} keeps their likes private. Only they can download them, by signing in \
+             with --authorize.",
+            blog
+        ),
+        404 if blog.is_empty() => "Tumblr couldn't find your likes.".to_string(),
+        404 => format!(
+            "The blog {} doesn't exist. Make sure the blog name is right.",
+            blog
+        ),
+        429 => "You've hit Tumblr's rate limit. Wait a while, the limits reset every \
+             hour and every day, then try again."
+            .to_string(),
+        _ if msg.is_empty() => format!("Tumblr returned an error ({}).", status),
+        _ => format!("Tumblr returned an error ({} {}).", status, msg),
+    };
+
+    Error::Api(format!("{}{}", message, details))
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Api(message) | Error::Transfer(message) | Error::Config(message) => {
+                write!(f, "{}", message)
+            }
+            Error::DailyLimit { reset, .. } => write!(
+                f,
+                "Used up today's API requests before getting every like. They reset in {}, \
+                pass --wait-on-limit to wait for them.",
+                minutes(*reset)
+            ),
+            Error::Http { context, source } => write!(f, "{}: {}", context, source),
+            Error::Io { context, source } => write!(f, "{}: {}", context, source),
+            Error::Parse { context, source } => write!(f, "{}: {}", context, source),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Api(_) | Error::DailyLimit { .. } | Error::Transfer(_) | Error::Config(_) => {
+                None
+            }
+            Error::Http { source, .. } => Some(source),
+            Error::Io { source, .. } => Some(source),
+            Error::Parse { source, .. } => Some(source),
+        }
+    }
+}
+
+/// Turns lower level errors into an [`Error`], describing what was being done.
+pub trait Context<T> {
+    fn with_context<S: Into<String>, F: FnOnce() -> S>(self, context: F) -> Result<T>;
+}
+
+impl<T> Context<T> for std::result::Result<T, reqwest::Error> {
+    fn with_context<S: Into<String>, F: FnOnce() -> S>(self, context: F) -> Result<T> {
+        self.map_err(|source| Error::Http {
+            context: context().into(),
+            source,
+        })
+    }
+}
+
+impl<T> Context<T> for std::result::Result<T, io::Error> {
+    fn with_context<S: Into<String>, F: FnOnce() -> S>(self, context: F) -> Result<T> {
+        self.map_err(|source| Error::Io {
+            context: context().into(),
+            source,
+        })
+    }
+}
+
+impl<T> Context<T> for std::result::Result<T, serde_json::Error> {
+    fn with_context<S: Into<String>, F: FnOnce() -> S>(self, context: F) -> Result<T> {
+        self.map_err(|source| Error::Parse {
+            context: context().into(),
+            source,
+        })
+    }
+}
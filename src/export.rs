@@ -0,0 +1,93 @@
+use indicatif::ProgressBar;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::download::{post_media, Downloader};
+use crate::error::{Context, Result};
+use crate::types::Post;
+use crate::util::warn;
+
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    /// Where media is fetched to, if it wasn't downloaded already. Exports
+    /// link to it, and to the download directory, as seen from the current directory.
+    pub media_directory: String,
+}
+
+impl Default for ExportOptions {
+    fn default() -> ExportOptions {
+        ExportOptions {
+            media_directory: "export".to_string(),
+        }
+    }
+}
+
+/// A post along with where its media was saved.
+#[derive(Debug, Clone)]
+pub struct ResolvedPost {
+    pub post: Post,
+    /// Local paths by media URL. Media that could not be fetched is missing.
+    pub media: HashMap<String, PathBuf>,
+}
+
+/// Writes out liked posts in some format, like [`crate::HtmlExporter`].
+pub trait Exporter {
+    /// Whether `post` is exported at all. Other posts' media isn't fetched.
+    fn includes(&self, _post: &Post) -> bool {
+        true
+    }
+
+    fn export(&self, posts: &[ResolvedPost]) -> Result<()>;
+}
+
+/// Fetches the media of every post into the media directory, reusing any
+/// that was already downloaded.
+pub async fn resolve(
+    downloader: &Downloader,
+    options: &ExportOptions,
+    posts: Vec<Post>,
+    bar: &ProgressBar,
+) -> Result<Vec<ResolvedPost>> {
+    let directory = &options.media_directory;
+    let photo_size = &downloader.options().photo_size;
+
+    fs::create_dir_all(directory).with_context(|| format!("Could not create {}", directory))?;
+
+    let mut resolved = Vec::new();
+
+    for post in posts {
+        let mut media = HashMap::new();
+
+        for (_, url) in post_media(&post, photo_size) {
+            match downloader.fetch_into(directory, post.id, url.clone()).await {
+                Ok(path) => {
+                    media.insert(url, path);
+                }
+                Err(e) => warn(bar, e.to_string()),
+            }
+        }
+
+        resolved.push(ResolvedPost { post, media });
+        bar.inc(1);
+    }
+
+    Ok(resolved)
+}
+
+/// Fetches the media of `posts`, then hands them to `exporter`.
+pub async fn export(
+    downloader: &Downloader,
+    options: &ExportOptions,
+    exporter: &dyn Exporter,
+    posts: Vec<Post>,
+    bar: &ProgressBar,
+) -> Result<()> {
+    let (posts, skipped): (Vec<Post>, Vec<Post>) =
+        posts.into_iter().partition(|p| exporter.includes(p));
+    bar.inc(skipped.len() as u64);
+
+    let posts = resolve(downloader, options, posts, bar).await?;
+
+    exporter.export(&posts)
+}
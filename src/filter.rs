@@ -0,0 +1,221 @@
+use crate::types::{Post, PostKind};
+
+/// The types `--type` can pick.
+pub const POST_TYPES: &[&str] = PostKind::TYPES;
+
+/// Which liked posts to keep. Empty lists and `None` don't filter anything.
+#[derive(Debug, Default, Clone)]
+pub struct Filter {
+    pub types: Vec<String>,
+    pub tags: Vec<String>,
+    pub exclude_tags: Vec<String>,
+    pub blogs: Vec<String>,
+    pub exclude_blogs: Vec<String>,
+    /// Unix timestamps, compared against when the post was made.
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+    pub min_notes: Option<u64>,
+}
+
+impl Filter {
+    /// Whether every post is kept.
+    pub fn is_empty(&self) -> bool {
+        self.types.is_empty()
+            && self.tags.is_empty()
+            && self.exclude_tags.is_empty()
+            && self.blogs.is_empty()
+            && self.exclude_blogs.is_empty()
+            && self.since.is_none()
+            && self.until.is_none()
+            && self.min_notes.is_none()
+    }
+
+    pub fn matches(&self, post: &Post) -> bool {
+        let has_tag = |tags: &[String]| {
+            post.tags
+                .iter()
+                .any(|t| tags.iter().any(|f| f.eq_ignore_ascii_case(t)))
+        };
+        let from_blog = |blogs: &[String]| {
+            blogs
+                .iter()
+                .any(|b| b.eq_ignore_ascii_case(&post.blog_name))
+        };
+
+        (self.types.is_empty() || self.types.iter().any(|t| t == post.type_name()))
+            && (self.tags.is_empty() || has_tag(&self.tags))
+            && !has_tag(&self.exclude_tags)
+            && (self.blogs.is_empty() || from_blog(&self.blogs))
+            && !from_blog(&self.exclude_blogs)
+            && self.since.is_none_or(|since| post.timestamp >= since)
+            && self.until.is_none_or(|until| post.timestamp < until)
+            && self.min_notes.is_none_or(|min| post.note_count >= min)
+    }
+}
+
+/// Parses a Unix timestamp, or a `YYYY-MM-DD` date as midnight UTC.
+pub fn parse_time(s: &str) -> Result<u64, String> {
+    if let Ok(timestamp) = s.parse::<u64>() {
+        return Ok(timestamp);
+    }
+
+    let invalid = || format!("\"{}\" is not a YYYY-MM-DD date or Unix timestamp", s);
+    let parts: Vec<&str> = s.split('-').collect();
+
+    let (y, m, d) = match parts.as_slice() {
+        [y, m, d] => (
+            y.parse::<i64>().map_err(|_| invalid())?,
+            m.parse::<i64>().map_err(|_| invalid())?,
+            d.parse::<i64>().map_err(|_| invalid())?,
+        ),
+        _ => return Err(invalid()),
+    };
+
+    if !(1970..=9999).contains(&y) || !(1..=12).contains(&m) || !(1..=days_in(y, m)).contains(&d) {
+        return Err(invalid());
+    }
+
+    // Days since 1970-01-01, from Howard Hinnant's days_from_civil
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y / 400;
+    let yoe = y - era * 400;
+    let mp = (m + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+
+    Ok(days as u64 * 86400)
+}
+
+/// How many days month `m` of year `y` has.
+fn days_in(y: i64, m: i64) -> i64 {
+    match m {
+        2 if y % 4 == 0 && (y % 100 != 0 || y % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn post() -> Post {
+        serde_json::from_value(json!({
+            "id": 1,
+            "type": "photo",
+            "blog_name": "Some-Blog",
+            "timestamp": 1600000000,
+            "note_count": 10,
+            "tags": ["Cats", "dogs"],
+        }))
+        .unwrap()
+    }
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_dates_and_timestamps() {
+        assert_eq!(parse_time("1600000000"), Ok(1600000000));
+        assert_eq!(parse_time("1970-01-01"), Ok(0));
+        assert_eq!(parse_time("2020-09-13"), Ok(1599955200));
+        assert_eq!(parse_time("2024-02-29"), Ok(1709164800));
+        assert_eq!(parse_time("2000-02-29"), Ok(951782400));
+        assert_eq!(parse_time("2021-12-31"), Ok(1640908800));
+    }
+
+    #[test]
+    fn rejects_days_the_month_does_not_have() {
+        for date in [
+            "2024-02-30",
+            "2021-02-29",
+            "1900-02-29",
+            "2100-02-29",
+            "2021-04-31",
+            "2021-13-01",
+            "2021-00-10",
+            "2021-01-00",
+            "1969-12-31",
+            "2021-01",
+            "yesterday",
+        ] {
+            assert!(parse_time(date).is_err(), "{}", date);
+        }
+    }
+
+    #[test]
+    fn keeps_everything_without_filters() {
+        let filter = Filter::default();
+
+        assert!(filter.is_empty());
+        assert!(filter.matches(&post()));
+    }
+
+    #[test]
+    fn filters_by_type() {
+        let filter = |types: &[&str]| Filter {
+            types: strings(types),
+            ..Filter::default()
+        };
+
+        assert!(filter(&["video", "photo"]).matches(&post()));
+        assert!(!filter(&["video"]).matches(&post()));
+    }
+
+    #[test]
+    fn filters_by_tag_ignoring_case() {
+        let filter = |tags: &[&str], exclude_tags: &[&str]| Filter {
+            tags: strings(tags),
+            exclude_tags: strings(exclude_tags),
+            ..Filter::default()
+        };
+
+        assert!(filter(&["cats"], &[]).matches(&post()));
+        assert!(!filter(&["birds"], &[]).matches(&post()));
+        assert!(!filter(&[], &["DOGS"]).matches(&post()));
+        assert!(!filter(&["cats"], &["dogs"]).matches(&post()));
+    }
+
+    #[test]
+    fn filters_by_blog_ignoring_case() {
+        let filter = |blogs: &[&str], exclude_blogs: &[&str]| Filter {
+            blogs: strings(blogs),
+            exclude_blogs: strings(exclude_blogs),
+            ..Filter::default()
+        };
+
+        assert!(filter(&["some-blog"], &[]).matches(&post()));
+        assert!(!filter(&["other"], &[]).matches(&post()));
+        assert!(!filter(&[], &["SOME-BLOG"]).matches(&post()));
+    }
+
+    #[test]
+    fn filters_by_date() {
+        let filter = |since, until| Filter {
+            since,
+            until,
+            ..Filter::default()
+        };
+
+        // Since is inclusive, until isn't
+        assert!(filter(Some(1600000000), None).matches(&post()));
+        assert!(!filter(Some(1600000001), None).matches(&post()));
+        assert!(filter(None, Some(1600000001)).matches(&post()));
+        assert!(!filter(None, Some(1600000000)).matches(&post()));
+    }
+
+    #[test]
+    fn filters_by_notes() {
+        let filter = |min_notes| Filter {
+            min_notes: Some(min_notes),
+            ..Filter::default()
+        };
+
+        assert!(filter(10).matches(&post()));
+        assert!(!filter(11).matches(&post()));
+    }
+}
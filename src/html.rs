@@ -0,0 +1,252 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::error::{Context, Result};
+use crate::export::{Exporter, ResolvedPost};
+use crate::npf;
+use crate::types::{Post, PostKind, TrailItem};
+use crate::util::html_media;
+
+/// Exports posts as a single HTML page, styled with Bulma. Text, photo and
+/// video posts are exported, along with any post in the Neue Post Format.
+#[derive(Debug, Clone)]
+pub struct HtmlExporter {
+    /// The HTML file to write.
+    pub file: String,
+}
+
+impl HtmlExporter {
+    pub fn new(file: &str) -> HtmlExporter {
+        HtmlExporter {
+            file: file.to_string(),
+        }
+    }
+}
+
+static HTML_TEMPLATE: &str = "<!DOCTYPE html>
+<html lang='en'>
+<head>
+    <meta charset='UTF-8'>
+    <meta name='viewport' content='width=device-width, initial-scale=1'>
+    <title>Tumblr Likes</title>
+    <link rel='stylesheet' href='https://cdnjs.cloudflare.com/ajax/libs/bulma/0.7.2/css/bulma.min.css'>
+    <style>
+        .container {
+            max-width: 625px;
+        }
+
+        .card {
+            margin-top: 20px;
+            margin-bottom: 20px;
+        }
+    </style>
+</head>
+<body>
+    <div class='container'>
+        {{cards}}
+    </div>
+</body>
+</html>
+";
+
+static CARD_TEMPLATE: &str = "<div class='card'>
+    <div class='card-header'>
+        <div class='card-header-title'>
+            {{title}}
+        </div>
+    </div>
+
+    <div class='card-content'>
+        <div class='content'>
+            {{body}}
+        </div>
+        {{tags}}
+        <div class='tags'>
+            <span class='tag'>{{date}}</span>
+            <span class='tag'>{{note_count}} notes</span>
+        </div>
+    </div>
+</div>
+";
+
+impl Exporter for HtmlExporter {
+    fn includes(&self, post: &Post) -> bool {
+        post.content.is_some()
+            || matches!(
+                post.kind,
+                PostKind::Text(_) | PostKind::Photo(_) | PostKind::Video(_)
+            )
+    }
+
+    fn export(&self, posts: &[ResolvedPost]) -> Result<()> {
+        let out = HTML_TEMPLATE.replace("{{cards}}", &render(posts));
+
+        fs::write(&self.file, out).with_context(|| format!("Couldn't write to {}", self.file))
+    }
+}
+
+/// Renders a card for each post that can be exported.
+fn render(posts: &[ResolvedPost]) -> String {
+    let mut posts_html = String::new();
+
+    for ResolvedPost { post, media } in posts {
+        let title = format!("<a href='{}'>{}</a>", post.post_url, post.blog_name);
+        let mut card = CARD_TEMPLATE.replace("{{title}}", &title);
+
+        if !post.tags.is_empty() {
+            let tags = format!(
+                "<div class='tags'><span class='tag'>{}</span></div>",
+                post.tags.join("</span><span class='tag'>")
+            );
+            card = card.replace("{{tags}}", &tags);
+        } else {
+            card = card.replace("{{tags}}", "");
+        }
+
+        card = card.replace("{{date}}", &post.date);
+        card = card.replace("{{note_count}}", &post.note_count.to_string());
+
+        if let Some(content) = &post.content {
+            // Neue Post Format
+            let local: HashMap<String, String> = media
+                .iter()
+                .map(|(url, path)| (url.clone(), path.to_string_lossy().to_string()))
+                .collect();
+
+            let mut body = String::new();
+
+            if let Some(mut trail) = post.trail.clone() {
+                for item in trail.iter_mut() {
+                    if let Some(content) = &item.content {
+                        item.content_raw = npf::render(content, item.layout.as_deref(), &local);
+                    }
+                }
+
+                body = render_trail(trail).replace("{{content}}", "");
+            }
+
+            body.push_str(&npf::render(content, post.layout.as_deref(), &local));
+
+            card = card.replace("{{body}}", &body);
+            posts_html = format!("{}{}", posts_html, card);
+        } else {
+            let local = |url: &str| media.get(url).map(|path| path.as_path());
+
+            match &post.kind {
+                PostKind::Text(text) => {
+                    if let Some(body) = &text.body {
+                        let mut content = body.clone();
+
+                        // Replace all objects in the body with locally stored ones
+                        for (_, url) in html_media(body) {
+                            content = content.replace(
+                                &url,
+                                &inject_content(local(&url), "Could not fetch object", |path| {
+                                    path.to_string_lossy().to_string()
+                                }),
+                            );
+                        }
+
+                        card = card.replace("{{body}}", &content);
+                        posts_html = format!("{}{}", posts_html, card);
+                    }
+                }
+                PostKind::Video(video) => {
+                    let mut body = String::new();
+
+                    if let Some(trail) = post.trail.clone() {
+                        let mut trail_content = render_trail(trail);
+
+                        // Inject video
+                        if let Some(url) = &video.video_url {
+                            trail_content = trail_content.replace(
+                                "{{content}}",
+                                &inject_content(local(url), "Could not fetch video", |path| {
+                                    format!(
+                                        "<p><figure><video controls='controls' autoplay='autoplay' \
+                                         muted='muted'><source src='{}'></video></figure></p>",
+                                        path.to_string_lossy()
+                                    )
+                                }),
+                            );
+                        }
+
+                        trail_content = trail_content.replace("{{content}}", "");
+                        body = trail_content;
+                    }
+
+                    card = card.replace("{{body}}", &body);
+                    posts_html = format!("{}{}", posts_html, card);
+                }
+                PostKind::Photo(photo) => {
+                    let mut body = String::new();
+
+                    if let Some(trail) = post.trail.clone() {
+                        let mut trail_content = render_trail(trail);
+
+                        // Inject photos, in whichever size was fetched
+                        for photo in &photo.photos {
+                            let path = photo.sizes().find_map(|size| local(&size.url));
+
+                            trail_content = trail_content.replace(
+                                "{{content}}",
+                                &inject_content(path, "Could not fetch photo", |path| {
+                                    format!(
+                                        "<figure><img src='{}' /></figure>{{{{content}}}}",
+                                        path.to_string_lossy()
+                                    )
+                                }),
+                            );
+                        }
+
+                        trail_content = trail_content.replace("{{content}}", "");
+                        body = trail_content;
+                    }
+
+                    card = card.replace("{{body}}", &body);
+                    posts_html = format!("{}{}", posts_html, card);
+                }
+                // Not included
+                PostKind::Quote(_)
+                | PostKind::Link(_)
+                | PostKind::Chat(_)
+                | PostKind::Audio(_)
+                | PostKind::Answer(_)
+                | PostKind::Blocks
+                | PostKind::Unknown => {}
+            }
+        }
+    }
+
+    posts_html
+}
+
+fn render_trail(trail: Vec<TrailItem>) -> String {
+    let mut trail_content = "{{content}}".to_string();
+
+    for item in trail.iter().rev() {
+        let blog = &item.blog.name;
+        let id = &item.post.id;
+        let content = &item.content_raw;
+
+        let url = format!("https://{}.tumblr.com/post/{}/", blog, id);
+        let name = format!("<p><a href='{}'>{}</a>:</p>", url, blog);
+
+        let combined = format!(
+            "{}<blockquote>{{{{content}}}}{}</blockquote>",
+            name, content
+        );
+
+        trail_content = trail_content.replace("{{content}}", &combined);
+    }
+
+    trail_content
+}
+
+fn inject_content<F: Fn(&Path) -> String>(raw: Option<&Path>, error_text: &str, cb: F) -> String {
+    match raw {
+        Some(path) => cb(path),
+        None => error_text.to_string(),
+    }
+}
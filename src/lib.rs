@@ -0,0 +1,57 @@
+//! Archives liked posts on Tumblr: pages through a blog's likes (or your own),
+//! downloads their photos, videos and audio to a directory or an S3 bucket,
+//! and dumps or exports them.
+//!
+//! ```no_run
+//! use futures::TryStreamExt;
+//! use indicatif::ProgressBar;
+//! use tumblr_likes::{Client, ClientOptions, DownloadOptions, Downloader, Likes, LikesSource, Manifest};
+//!
+//! # async fn run() -> tumblr_likes::Result<()> {
+//! let client = Client::new(ClientOptions::new("api key"))?;
+//! let options = DownloadOptions::default();
+//! let manifest = Manifest::load(&options.directory).unwrap();
+//! let downloader = Downloader::new(&client, options, manifest);
+//!
+//! let likes = Likes::Blog("staff".to_string());
+//! let posts = LikesSource::new(&client, likes).posts().try_collect().await?;
+//! let bar = ProgressBar::hidden();
+//!
+//! let pending = downloader.download_posts(posts, &bar);
+//! downloader.finish(pending, &bar).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod client;
+pub mod download;
+pub mod dump;
+pub mod error;
+pub mod export;
+pub mod filter;
+pub mod html;
+pub mod manifest;
+mod npf;
+pub mod oauth;
+pub mod photo_size;
+pub mod rate_limit;
+pub mod retry;
+pub mod s3;
+pub mod source;
+pub mod storage;
+pub mod sync;
+pub mod template;
+pub mod types;
+mod util;
+
+pub use crate::client::{Client, ClientOptions, Likes, Page};
+pub use crate::download::{DownloadOptions, Downloader, PostDownload, PostFiles, SavedFile};
+pub use crate::error::{Error, Result};
+pub use crate::export::{export, ExportOptions, Exporter, ResolvedPost};
+pub use crate::filter::Filter;
+pub use crate::html::HtmlExporter;
+pub use crate::manifest::Manifest;
+pub use crate::s3::{S3Options, S3Storage};
+pub use crate::source::LikesSource;
+pub use crate::storage::{LocalStorage, Storage};
+pub use crate::types::Post;
@@ -1,52 +1,106 @@
 use clap::{crate_version, App, Arg};
+use futures::stream::{self, Stream, StreamExt};
 use indicatif::ProgressBar;
-use regex::Regex;
+use std::collections::HashSet;
 use std::env;
-use std::error::Error;
-use std::fs::{self, File};
-use std::io::{BufReader, Write};
-use std::path::{Path, PathBuf};
-
-mod types;
-mod util;
-
-use crate::types::*;
-use crate::util::*;
+use std::pin::Pin;
+use std::process;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tumblr_likes::client::API_HOST;
+use tumblr_likes::error::Context;
+use tumblr_likes::filter::{parse_time, POST_TYPES};
+use tumblr_likes::oauth::{self, OAuthCredentials};
+use tumblr_likes::photo_size::PhotoSize;
+use tumblr_likes::rate_limit::minutes;
+use tumblr_likes::retry::RetryPolicy;
+use tumblr_likes::sync::{Checkpoint, Marker, SyncState};
+use tumblr_likes::template::{Template, DEFAULT_TEMPLATE};
+use tumblr_likes::{
+    dump, export, Client, ClientOptions, DownloadOptions, Downloader, Error, ExportOptions, Filter,
+    HtmlExporter, Likes, LikesSource, LocalStorage, Manifest, Post, PostDownload, Result,
+    S3Options, S3Storage, Storage,
+};
 
 #[derive(Debug)]
-pub struct Arguments {
-    api_key: String,
-    blog_name: String,
-    directory: String,
+struct Arguments {
+    client: ClientOptions,
+    download: DownloadOptions,
+    /// Where to upload files, instead of the download directory.
+    s3: Option<S3Options>,
+    consumer_secret: Option<String>,
+    authorize: bool,
+    /// Whose likes to fetch, unless restoring a dump.
+    likes: Option<Likes>,
     dump: Option<String>,
     restore: Option<String>,
     export: Option<String>,
+    filter: Filter,
+    incremental: bool,
+    verify: bool,
+    wait_on_limit: bool,
     verbose: bool,
 }
 
 fn cli() -> Arguments {
     let env_key = env::var("TUMBLR_API_KEY");
+    let env_token = env::var("TUMBLR_OAUTH_TOKEN");
+
+    let mut blog_name = Arg::with_name("BLOG_NAME")
+        .short('b')
+        .help("The blog to download likes from")
+        .takes_value(true)
+        .conflicts_with("JSON_RESTORE");
+
+    // Signed in users download their own likes instead
+    if env_token.is_err() {
+        blog_name =
+            blog_name.required_unless_present_any(["OAUTH_TOKEN", "authorize", "JSON_RESTORE"]);
+    }
 
     let matches = App::new("tumblr-likes")
         .version(crate_version!())
         .author("Alex Taylor <alex@alext.xyz>")
-        .about("Downloads your liked photos and videos on Tumblr.")
+        .about("Downloads your liked photos, videos and audio on Tumblr.")
         .arg(
             Arg::with_name("API_KEY")
                 .short('a')
                 .help("Your Tumblr API key")
                 .takes_value(true)
-                .required(env_key.is_err())
+                .required_unless_present_any(if env_key.is_err() {
+                    &["JSON_RESTORE"][..]
+                } else {
+                    &[]
+                })
                 .conflicts_with("JSON_RESTORE"),
         )
         .arg(
-            Arg::with_name("BLOG_NAME")
-                .short('b')
-                .help("The blog to download likes from")
+            Arg::with_name("CONSUMER_SECRET")
+                .long("consumer-secret")
+                .help("Your Tumblr OAuth consumer secret, for private likes")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("OAUTH_TOKEN")
+                .long("token")
+                .help("Your OAuth access token. Downloads your own likes, even if they're private")
                 .takes_value(true)
-                .required(true)
                 .conflicts_with("JSON_RESTORE"),
         )
+        .arg(
+            Arg::with_name("OAUTH_TOKEN_SECRET")
+                .long("token-secret")
+                .help("Your OAuth access token secret")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("authorize")
+                .long("authorize")
+                .help("Signs in to Tumblr in your browser to get an OAuth access token")
+                .conflicts_with_all(&["JSON_RESTORE", "OAUTH_TOKEN"]),
+        )
+        .arg(blog_name)
         .arg(
             Arg::with_name("OUTPUT_DIR")
                 .short('d')
@@ -54,6 +108,62 @@ fn cli() -> Arguments {
                 .help("The download directory")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("API_HOST")
+                .long("api-host")
+                .help("Sends API requests here instead of https://api.tumblr.com")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("MEDIA_HOST")
+                .long("media-host")
+                .help("Downloads media on Tumblr's servers from here instead")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("S3_BUCKET")
+                .long("s3-bucket")
+                .help(
+                    "Uploads files to this S3 bucket instead of keeping them in the download \
+                    directory. Credentials come from AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY",
+                )
+                .takes_value(true)
+                .conflicts_with_all(&["JSON_DUMP", "HTML_FILE"]),
+        )
+        .arg(
+            Arg::with_name("S3_ENDPOINT")
+                .long("s3-endpoint")
+                .help("Talks to this S3 compatible server, like MinIO, instead of Amazon's")
+                .takes_value(true)
+                .requires("S3_BUCKET"),
+        )
+        .arg(
+            Arg::with_name("S3_REGION")
+                .long("s3-region")
+                .help("The bucket's region")
+                .takes_value(true)
+                .requires("S3_BUCKET"),
+        )
+        .arg(
+            Arg::with_name("S3_PREFIX")
+                .long("s3-prefix")
+                .help("Puts files under this prefix in the bucket, like likes/")
+                .takes_value(true)
+                .requires("S3_BUCKET"),
+        )
+        .arg(
+            Arg::with_name("TEMPLATE")
+                .short('t')
+                .long("template")
+                .help(
+                    "Where to save each file inside the download directory. Placeholders: \
+                    {blog_name}, {id}, {kind}, {folder}, {date}, {year}, {month}, {day}, \
+                    {timestamp}, {liked_timestamp}, {tags}, {index}, {filename}, {ext}",
+                )
+                .takes_value(true)
+                .default_value(DEFAULT_TEMPLATE)
+                .validator(|t| t.parse::<Template>().map(|_| ())),
+        )
         .arg(
             Arg::with_name("JSON_DUMP")
                 .long("dump")
@@ -74,6 +184,140 @@ fn cli() -> Arguments {
                 .help("Exports liked posts into the given HTML file")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("JOBS")
+                .short('j')
+                .long("jobs")
+                .help("The maximum number of concurrent downloads")
+                .takes_value(true)
+                .default_value("4")
+                .validator(|j| match j.parse::<usize>() {
+                    Ok(n) if n > 0 => Ok(()),
+                    _ => Err("must be a positive number"),
+                }),
+        )
+        .arg(
+            Arg::with_name("RETRIES")
+                .long("retries")
+                .help("How many times to retry a failed request")
+                .takes_value(true)
+                .default_value("5")
+                .validator(|r| r.parse::<u32>().map(|_| ()).map_err(|e| e.to_string())),
+        )
+        .arg(
+            Arg::with_name("RETRY_DELAY")
+                .long("retry-delay")
+                .help("Milliseconds to wait before the first retry, doubling after each attempt")
+                .takes_value(true)
+                .default_value("1000")
+                .validator(|d| d.parse::<u64>().map(|_| ()).map_err(|e| e.to_string())),
+        )
+        .arg(
+            Arg::with_name("npf")
+                .long("npf")
+                .help("Requests posts in the Neue Post Format")
+                .conflicts_with("JSON_RESTORE"),
+        )
+        .arg(
+            Arg::with_name("PHOTO_SIZE")
+                .long("photo-size")
+                .help(
+                    "Which size of each photo to download: largest, above:<width> for the \
+                    smallest one at least that wide, or width:<width> for an exact width",
+                )
+                .takes_value(true)
+                .default_value("largest")
+                .validator(|s| s.parse::<PhotoSize>().map(|_| ())),
+        )
+        .arg(
+            Arg::with_name("upscale")
+                .long("upscale")
+                .help("Tries to download photos larger than the largest size the API lists"),
+        )
+        .arg(
+            Arg::with_name("TYPE")
+                .long("type")
+                .help("Only keeps posts of these types")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .use_value_delimiter(true)
+                .possible_values(POST_TYPES),
+        )
+        .arg(
+            Arg::with_name("TAG")
+                .long("tag")
+                .help("Only keeps posts with any of these tags")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .use_value_delimiter(true),
+        )
+        .arg(
+            Arg::with_name("EXCLUDE_TAG")
+                .long("exclude-tag")
+                .help("Leaves out posts with any of these tags")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .use_value_delimiter(true),
+        )
+        .arg(
+            Arg::with_name("FROM_BLOG")
+                .long("from-blog")
+                .help("Only keeps posts from these blogs")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .use_value_delimiter(true),
+        )
+        .arg(
+            Arg::with_name("EXCLUDE_BLOG")
+                .long("exclude-blog")
+                .help("Leaves out posts from these blogs")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .use_value_delimiter(true),
+        )
+        .arg(
+            Arg::with_name("SINCE")
+                .long("since")
+                .help("Only keeps posts made on or after this date (YYYY-MM-DD) or Unix timestamp")
+                .takes_value(true)
+                .validator(|t| parse_time(t).map(|_| ())),
+        )
+        .arg(
+            Arg::with_name("UNTIL")
+                .long("until")
+                .help("Only keeps posts made before this date (YYYY-MM-DD) or Unix timestamp")
+                .takes_value(true)
+                .validator(|t| parse_time(t).map(|_| ())),
+        )
+        .arg(
+            Arg::with_name("MIN_NOTES")
+                .long("min-notes")
+                .help("Only keeps posts with at least this many notes")
+                .takes_value(true)
+                .validator(|n| n.parse::<u64>().map(|_| ()).map_err(|e| e.to_string())),
+        )
+        .arg(
+            Arg::with_name("incremental")
+                .short('i')
+                .long("incremental")
+                .help("Stops at the newest like downloaded by a previous run")
+                .conflicts_with_all(&["JSON_DUMP", "JSON_RESTORE", "HTML_FILE"]),
+        )
+        .arg(
+            Arg::with_name("verify")
+                .long("verify")
+                .help(
+                    "Checks previously downloaded files, downloading missing or incomplete ones \
+                    again",
+                )
+                // An incremental run would stop before the posts that need them
+                .conflicts_with("incremental"),
+        )
+        .arg(
+            Arg::with_name("wait_on_limit")
+                .long("wait-on-limit")
+                .help("Waits for the daily API limit to reset instead of stopping"),
+        )
         .arg(
             Arg::with_name("verbose")
                 .short('v')
@@ -82,428 +326,404 @@ fn cli() -> Arguments {
         )
         .get_matches();
 
-    Arguments {
-        api_key: match matches.value_of("API_KEY") {
-            Some(a) => a.to_string(),
-            None => {
-                if !env_key.is_err() {
-                    env_key.unwrap().to_string()
-                } else {
-                    "".to_string()
-                }
-            }
-        },
+    let api_key = match matches.value_of("API_KEY") {
+        Some(a) => a.to_string(),
+        None => env_key.unwrap_or_default(),
+    };
 
-        blog_name: match matches.value_of("BLOG_NAME") {
-            Some(b) => b.to_string(),
-            None => "".to_string(),
-        },
+    let list = |name| -> Vec<String> {
+        matches
+            .values_of(name)
+            .map(|v| v.map(|s| s.to_string()).collect())
+            .unwrap_or_default()
+    };
 
-        directory: match matches.value_of("OUTPUT_DIR") {
-            Some(d) => d.to_string(),
-            None => "downloads".to_string(),
-        },
+    let filter = Filter {
+        types: list("TYPE"),
+        tags: list("TAG"),
+        exclude_tags: list("EXCLUDE_TAG"),
+        blogs: list("FROM_BLOG"),
+        exclude_blogs: list("EXCLUDE_BLOG"),
+        since: matches.value_of("SINCE").map(|t| parse_time(t).unwrap()),
+        until: matches.value_of("UNTIL").map(|t| parse_time(t).unwrap()),
+        min_notes: matches.value_of("MIN_NOTES").map(|n| n.parse().unwrap()),
+    };
+
+    let consumer_secret = matches
+        .value_of("CONSUMER_SECRET")
+        .map(|s| s.to_string())
+        .or_else(|| env::var("TUMBLR_CONSUMER_SECRET").ok());
+    let token = matches
+        .value_of("OAUTH_TOKEN")
+        .map(|s| s.to_string())
+        .or_else(|| env_token.ok());
+    let token_secret = matches
+        .value_of("OAUTH_TOKEN_SECRET")
+        .map(|s| s.to_string())
+        .or_else(|| env::var("TUMBLR_OAUTH_TOKEN_SECRET").ok());
+
+    let oauth = match (&consumer_secret, token, token_secret) {
+        (Some(consumer_secret), Some(token), Some(token_secret)) => Some(OAuthCredentials {
+            consumer_key: api_key.clone(),
+            consumer_secret: consumer_secret.clone(),
+            token,
+            token_secret,
+        }),
+        _ => None,
+    };
+
+    let s3 = matches.value_of("S3_BUCKET").map(|bucket| {
+        let region = matches
+            .value_of("S3_REGION")
+            .map(|r| r.to_string())
+            .or_else(|| env::var("AWS_REGION").ok())
+            .unwrap_or_else(|| "us-east-1".to_string());
+
+        S3Options {
+            endpoint: matches
+                .value_of("S3_ENDPOINT")
+                .map(|e| e.to_string())
+                .or_else(|| env::var("AWS_ENDPOINT_URL").ok())
+                .unwrap_or_else(|| format!("https://s3.{}.amazonaws.com", region))
+                .trim_end_matches('/')
+                .to_string(),
+            bucket: bucket.to_string(),
+            region,
+            prefix: matches
+                .value_of("S3_PREFIX")
+                .unwrap_or_default()
+                .to_string(),
+            access_key: env::var("AWS_ACCESS_KEY_ID").unwrap_or_default(),
+            secret_key: env::var("AWS_SECRET_ACCESS_KEY").unwrap_or_default(),
+        }
+    });
+
+    // Signed in without a blog, the likes are the user's own. Blog likes are
+    // signed as well, so private ones the user can see are included.
+    let likes = match matches.value_of("BLOG_NAME") {
+        Some(b) => Some(Likes::Blog(b.to_string())),
+        None if oauth.is_some() => Some(Likes::User),
+        None => None,
+    };
 
+    Arguments {
+        client: ClientOptions {
+            api_key,
+            oauth,
+
+            // Hosts are joined with paths that start with a slash
+            api_host: matches
+                .value_of("API_HOST")
+                .map(|h| h.to_string())
+                .or_else(|| env::var("TUMBLR_API_HOST").ok())
+                .unwrap_or_else(|| API_HOST.to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            media_host: matches
+                .value_of("MEDIA_HOST")
+                .map(|h| h.to_string())
+                .or_else(|| env::var("TUMBLR_MEDIA_HOST").ok())
+                .map(|h| h.trim_end_matches('/').to_string()),
+
+            npf: matches.is_present("npf"),
+            retry: RetryPolicy {
+                max_attempts: matches
+                    .value_of("RETRIES")
+                    .unwrap()
+                    .parse::<u32>()
+                    .unwrap()
+                    .saturating_add(1),
+                base_delay: Duration::from_millis(
+                    matches.value_of("RETRY_DELAY").unwrap().parse().unwrap(),
+                ),
+            },
+        },
+        download: DownloadOptions {
+            directory: match matches.value_of("OUTPUT_DIR") {
+                Some(d) => d.to_string(),
+                None => "downloads".to_string(),
+            },
+            template: matches.value_of("TEMPLATE").unwrap().parse().unwrap(),
+            photo_size: matches.value_of("PHOTO_SIZE").unwrap().parse().unwrap(),
+            upscale: matches.is_present("upscale"),
+            jobs: matches.value_of("JOBS").unwrap().parse().unwrap(),
+        },
+        s3,
+        consumer_secret,
+        authorize: matches.is_present("authorize"),
+        likes,
         dump: matches.value_of("JSON_DUMP").map(|s| s.to_string()),
         restore: matches.value_of("JSON_RESTORE").map(|s| s.to_string()),
         export: matches.value_of("HTML_FILE").map(|s| s.to_string()),
+        filter,
+        incremental: matches.is_present("incremental"),
+        verify: matches.is_present("verify"),
+        wait_on_limit: matches.is_present("wait_on_limit"),
         verbose: matches.is_present("verbose"),
     }
 }
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
+async fn main() {
+    if let Err(e) = run().await {
+        eprintln!("Error: {}", e);
+        process::exit(e.exit_code());
+    }
+}
+
+async fn run() -> Result<()> {
     let args = cli();
-    let client = reqwest::Client::new();
+    let client = Client::new(args.client.clone())?;
     let bar;
     let mut all_posts: Vec<Post> = Vec::new();
-    let mut files: Vec<Vec<Option<PathBuf>>> = Vec::new();
-
-    if !args.restore.is_none() {
-        if args.verbose {
-            println!("Restoring dump...");
-        }
-
-        let posts = restore_dump(args.restore.clone().unwrap())?;
-        bar = ProgressBar::new(posts.len() as _);
+    let mut pending: Vec<PostDownload> = Vec::new();
+    let mut pending_posts: Vec<Marker> = Vec::new();
+    let mut sync: Option<SyncState> = None;
+    let mut newest = None;
+    let mut stopped_at = None;
+
+    if args.authorize {
+        let secret = args.consumer_secret.as_ref().ok_or_else(|| {
+            Error::Config(
+                "Signing in needs your consumer secret, pass it with --consumer-secret".to_string(),
+            )
+        })?;
+        let (token, token_secret) =
+            oauth::authorize(client.http(), &args.client.api_key, secret, |link| {
+                println!(
+                    "Open this link in your browser and allow access:\n\n    {}\n",
+                    link
+                )
+            })
+            .await?;
+
+        println!(
+            "Signed in! Pass these with --token and --token-secret, or set them in your environment:\n\n    \
+            TUMBLR_OAUTH_TOKEN={}\n    TUMBLR_OAUTH_TOKEN_SECRET={}",
+            token, token_secret
+        );
+        return Ok(());
+    }
 
-        // If not exporting, just do a download
-        if args.export.is_none() {
-            if args.verbose {
-                println!("Downloading posts...");
+    let storage: Arc<dyn Storage> = match &args.s3 {
+        Some(s3) => {
+            if s3.access_key.is_empty() || s3.secret_key.is_empty() {
+                return Err(Error::Config(
+                    "Uploading to S3 needs credentials, set AWS_ACCESS_KEY_ID and \
+                    AWS_SECRET_ACCESS_KEY"
+                        .to_string(),
+                ));
             }
 
-            files = download_posts(posts, &client, &args, &bar).await?;
-        } else {
-            all_posts = posts;
-        }
-    } else {
-        let info_url = build_url(&args, true, None);
-
-        if args.verbose {
-            println!("Info URL: {}", info_url);
-        }
-
-        let info = client.get(&info_url).send().await?;
-
-        if args.verbose {
-            println!("{:#?}", info);
+            Arc::new(S3Storage::new(&client, s3.clone())?)
         }
+        None => Arc::new(LocalStorage::new(&args.download.directory)),
+    };
 
-        if !info.status().is_success() {
-            println!(
-                "There was an error fetching your likes. Please make sure \
-                you provided the correct API key and blog name."
-            );
-            return Ok(());
-        }
+    let mut manifest =
+        Manifest::load(&args.download.directory).with_context(|| "Could not read the manifest")?;
 
-        let info: ReturnVal = info.json().await?;
+    if args.verify {
+        let broken = manifest.verify(storage.as_ref()).await?;
 
-        if args.verbose {
-            println!("Info: {:#?}", info);
+        for media in &broken {
+            println!("Missing or incomplete: {}", media.path.display());
         }
 
-        bar = ProgressBar::new(info.response.liked_count as _);
+        println!(
+            "Verified archive, {} files need downloading again.",
+            broken.len()
+        );
 
-        // Setup directory if not in export mode
-        if args.export.is_none() {
-            setup_directory(&args);
+        if !broken.is_empty() {
+            manifest
+                .save()
+                .with_context(|| "Could not save the manifest")?;
         }
+    }
 
-        // Do rip
-        let mut before = None;
-
-        if args.verbose {
-            println!("Downloading likes...");
-        }
+    let downloader = Downloader::new(&client, args.download.clone(), manifest).storing_in(storage);
 
-        loop {
-            let url = build_url(&args, false, before);
+    let mut posts: Pin<Box<dyn Stream<Item = Result<Post>>>> = match (&args.restore, &args.likes) {
+        (Some(restore), _) => {
+            if args.verbose {
+                println!("Restoring dump...");
+            }
 
-            let mut res: ReturnVal = client.get(&url).send().await?.json().await?;
-            let links = res.response._links;
+            let (posts, skipped) = dump::load(restore)?;
+            bar = ProgressBar::new(posts.len() as _);
 
-            if !args.dump.is_none() || !args.export.is_none() {
-                // If dumping or exporting, we need to collect every post
-                all_posts.append(&mut res.response.liked_posts);
-            } else {
-                files.append(
-                    &mut download_posts(res.response.liked_posts, &client, &args, &bar).await?,
-                );
+            for message in skipped {
+                bar.println(format!("Warning: {}", message));
             }
 
-            if let Some(l) = links {
-                before = if let Some(next) = l.next {
-                    Some(next.query_params.before)
-                } else {
-                    break;
-                };
-            } else {
-                break;
-            }
+            Box::pin(stream::iter(posts.into_iter().map(Ok)))
         }
-    }
+        (None, Some(likes)) => {
+            if args.verbose {
+                println!("Info URL: {}", client.likes_url(likes, 1, None));
+            }
 
-    // Dump
-    if let Some(dump_file) = args.dump {
-        dump(all_posts, dump_file);
-        return Ok(());
-    }
+            let info = client.likes_page(likes, 1, None).await?;
 
-    // Export
-    if let Some(export_file) = args.export {
-        export(&client, all_posts, export_file, &bar, args.verbose).await;
-        bar.finish();
-        return Ok(());
-    }
-
-    // Rename files with index
+            if args.verbose {
+                println!("Info: {:#?}", info);
+            }
 
-    if args.verbose {
-        println!("Renaming files...\n");
-    }
+            bar = ProgressBar::new(info.liked_count as _);
 
-    rename(files);
-    bar.finish();
+            if args.dump.is_none() && args.export.is_none() {
+                sync = Some(
+                    SyncState::load(&args.download.directory)
+                        .with_context(|| "Could not read the sync state")?,
+                );
+            }
 
-    Ok(())
-}
+            let mut source = LikesSource::new(&client, likes.clone())
+                .wait_on_limit(args.wait_on_limit)
+                .reporting_to(&bar);
 
-async fn download_posts(
-    posts: Vec<Post>,
-    client: &reqwest::Client,
-    args: &Arguments,
-    bar: &ProgressBar,
-) -> Result<Vec<Vec<Option<PathBuf>>>, Box<dyn Error>> {
-    let mut files: Vec<Vec<Option<PathBuf>>> = Vec::new();
-
-    for post in posts {
-        let mut post_files: Vec<Option<PathBuf>> = Vec::new();
-
-        if post.kind == "photo" {
-            if let Some(photos) = post.photos {
-                for photo in photos {
-                    post_files.push(download(client, args, "pics", photo.original_size.url).await?);
+            // Everything from the last seen post on was archived by a previous run
+            if let Some(marker) = sync.as_ref().and_then(|s| s.newest.get(likes.key())) {
+                if args.incremental {
+                    source = source.stopping_at(*marker);
                 }
             }
-        } else if post.kind == "video" {
-            if let Some(url) = post.video_url {
-                post_files.push(download(&client, &args, "videos", url).await?);
-            }
-        }
 
-        files.push(post_files);
-        bar.inc(1);
-    }
+            // Pick up where a run cut short by the daily limit stopped
+            if let Some(checkpoint) = sync.as_ref().and_then(|s| s.resume.get(likes.key())) {
+                if args.verbose {
+                    println!("Resuming from where the last run stopped...");
+                }
 
-    Ok(files)
-}
+                source = source.starting_before(checkpoint.before.clone());
+                newest = checkpoint.newest;
+            }
 
-fn rename(files: Vec<Vec<Option<PathBuf>>>) {
-    for (i, post) in files.iter().rev().enumerate() {
-        for file in post {
-            if let Some(file) = file {
-                let filename = &file.file_name().unwrap().to_str().unwrap();
+            if args.verbose {
+                println!("Downloading likes...");
+            }
 
-                let mut new_file = file.clone();
-                new_file.set_file_name(format!("{} - {}", i + 1, filename));
+            Box::pin(source.posts())
+        }
+        (None, None) => {
+            return Err(Error::Config(
+                "Downloading your own likes needs --consumer-secret, --token and \
+                --token-secret"
+                    .to_string(),
+            ));
+        }
+    };
 
-                fs::rename(&file, new_file).unwrap_or_else(|e| {
-                    panic!("Could not rename file! Error: {}", e);
-                });
+    while let Some(post) = posts.next().await {
+        let post = match post {
+            Ok(post) => post,
+            // Finish the downloads already started, then save our place
+            Err(Error::DailyLimit { resume, reset }) if sync.is_some() => {
+                stopped_at = Some((resume, reset));
+                break;
             }
+            Err(e) => return Err(e),
+        };
+
+        if !args.filter.matches(&post) {
+            bar.inc(1);
+        } else if args.dump.is_some() || args.export.is_some() {
+            // If dumping or exporting, we need to collect every post
+            all_posts.push(post);
+        } else {
+            // Downloads keep running in the background while we fetch more posts
+            pending_posts.push(Marker::new(&post));
+            pending.push(downloader.download_post(post, &bar));
         }
     }
-}
-
-fn dump(posts: Vec<Post>, file: String) {
-    let path = Path::new(&file);
-    let display = path.display();
 
-    let mut file = match File::create(&path) {
-        Ok(f) => f,
-        Err(e) => panic!("Couldn't create file {}: {}", display, e),
-    };
-
-    let json = serde_json::to_string(&posts).unwrap();
-
-    match file.write_all(json.as_bytes()) {
-        Ok(_) => println!("Dumped liked post data to {}.", display),
-        Err(e) => panic!("Couldn't write to {}: {}", display, e),
+    // Dump
+    if let Some(dump_file) = &args.dump {
+        dump::save(&all_posts, dump_file)?;
+        println!("Dumped liked post data to {}.", dump_file);
+        return Ok(());
     }
-}
-
-fn restore_dump(file: String) -> Result<Vec<Post>, Box<dyn Error>> {
-    let path = Path::new(&file);
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
 
-    let res: Vec<Post> = serde_json::from_reader(reader)?;
-    Ok(res)
-}
-
-static HTML_TEMPLATE: &'static str = "<!DOCTYPE html>
-<html lang='en'>
-<head>
-    <meta charset='UTF-8'>
-    <meta name='viewport' content='width=device-width, initial-scale=1'>
-    <title>Tumblr Likes</title>
-    <link rel='stylesheet' href='https://cdnjs.cloudflare.com/ajax/libs/bulma/0.7.2/css/bulma.min.css'>
-    <style>
-        .container {
-            max-width: 625px;
+    // Export
+    if let Some(export_file) = &args.export {
+        if args.verbose {
+            println!("Exporting your liked posts...");
         }
 
-        .card {
-            margin-top: 20px;
-            margin-bottom: 20px;
+        export(
+            &downloader,
+            &ExportOptions::default(),
+            &HtmlExporter::new(export_file),
+            all_posts,
+            &bar,
+        )
+        .await?;
+
+        if args.verbose {
+            println!("Exported liked posts to {}.", export_file);
         }
-    </style>
-</head>
-<body>
-    <div class='container'>
-        {{cards}}
-    </div>
-</body>
-</html>
-";
-
-static CARD_TEMPLATE: &'static str = "<div class='card'>
-    <div class='card-header'>
-        <div class='card-header-title'>
-            {{title}}
-        </div>
-    </div>
-
-    <div class='card-content'>
-        <div class='content'>
-            {{body}}
-        </div>
-        {{tags}}
-        <div class='tags'>
-            <span class='tag'>{{date}}</span>
-            <span class='tag'>{{note_count}} notes</span>
-        </div>
-    </div>
-</div>
-";
-
-async fn export(
-    client: &reqwest::Client,
-    posts: Vec<Post>,
-    file: String,
-    bar: &ProgressBar,
-    verbose: bool,
-) {
-    // Create export directory
-    fs::create_dir_all("export").expect("Could not create export directory!");
 
-    if verbose {
-        println!("Exporting your liked posts...");
+        bar.finish();
+        return Ok(());
     }
 
-    let mut posts_html = String::new();
-
-    for post in posts {
-        let title = format!("<a href='{}'>{}</a>", post.post_url, post.blog_name);
-        let mut card = CARD_TEMPLATE.replace("{{title}}", &title);
-
-        if post.tags.len() > 0 {
-            let tags = format!(
-                "<div class='tags'><span class='tag'>{}</span></div>",
-                post.tags.join("</span><span class='tag'>")
-            );
-            card = card.replace("{{tags}}", &tags);
-        } else {
-            card = card.replace("{{tags}}", "");
+    let complete: HashSet<u64> = downloader
+        .finish(pending, &bar)
+        .await?
+        .iter()
+        .filter(|post| post.is_complete())
+        .map(|post| post.id)
+        .collect();
+
+    // Later runs stop at the newest post that, along with every older one,
+    // was fully downloaded, so failed downloads are tried again
+    for marker in pending_posts {
+        if !complete.contains(&marker.id) {
+            newest = None;
+        } else if newest.is_none() {
+            newest = Some(marker);
         }
+    }
 
-        card = card.replace("{{date}}", &post.date);
-        card = card.replace("{{note_count}}", &post.note_count.to_string());
-
-        if post.kind == "text" {
-            if let Some(body) = post.body {
-                let mut content = body.clone();
-
-                // Extract URLs from body
-                let re = Regex::new(r#"src="([^"]+)"#).unwrap();
-                let caps = re.captures_iter(&body);
-
-                // Replace all objects with locally stored ones
-                for cap in caps {
-                    let url = cap.get(1).unwrap().as_str().to_string();
-                    let split: Vec<&str> = url.split("/").collect();
-                    let filename = split.last().unwrap();
-
-                    let dl =
-                        download_url(&client, url.clone(), format!("export/{}", filename)).await;
-
-                    content = content.replace(
-                        &url,
-                        &inject_content(dl, "Could not fetch object", |path| {
-                            let src = path.to_str().unwrap();
-                            src.to_string()
-                        }),
-                    );
-                }
-
-                card = card.replace("{{body}}", &content);
-                posts_html = format!("{}{}", posts_html, card);
-            }
-        } else if post.kind == "video" {
-            let mut body = String::new();
-
-            if let Some(trail) = post.trail {
-                let mut trail_content = render_trail(trail);
-
-                // Inject video
-                if let Some(url) = post.video_url {
-                    let split: Vec<&str> = url.split("/").collect();
-                    let filename = split.last().unwrap();
-
-                    let dl =
-                        download_url(&client, url.clone(), format!("export/{}", filename)).await;
-
-                    trail_content = trail_content.replace(
-                        "{{content}}",
-                        &inject_content(dl, "Could not fetch video", |path| {
-                            let src = path.to_str().unwrap();
-                            let video = format!(
-                                "<p><figure><video controls='controls' autoplay='autoplay' \
-                                 muted='muted'><source src='{}'></video></figure></p>",
-                                src
-                            );
-
-                            video
-                        }),
-                    );
-                }
-
-                trail_content = trail_content.replace("{{content}}", "");
-                body = trail_content;
+    if let (Some(mut sync), Some(likes)) = (sync, &args.likes) {
+        match &stopped_at {
+            Some((before, _)) => {
+                sync.resume.insert(
+                    likes.key().to_string(),
+                    Checkpoint {
+                        before: before.clone(),
+                        newest,
+                    },
+                );
             }
-
-            card = card.replace("{{body}}", &body);
-            posts_html = format!("{}{}", posts_html, card);
-        } else if post.kind == "photo" {
-            let mut body = String::new();
-
-            if let Some(trail) = post.trail {
-                let mut trail_content = render_trail(trail);
-
-                // Inject photos
-                if let Some(photos) = post.photos {
-                    for photo in photos {
-                        let url = photo.original_size.url;
-                        let split: Vec<&str> = url.split("/").collect();
-                        let filename = split.last().unwrap();
-                        let dl = download_url(&client, url.clone(), format!("export/{}", filename))
-                            .await;
-
-                        trail_content = trail_content.replace(
-                            "{{content}}",
-                            &inject_content(dl, "Could not fetch photo", |path| {
-                                let src = path.to_str().unwrap();
-                                let img = format!(
-                                    "<figure><img src='{}' /></figure>{{{{content}}}}",
-                                    src
-                                );
-
-                                img
-                            }),
-                        );
+            // Remember where this run started, for the next incremental run.
+            // Posts left out by filters weren't downloaded, so don't skip them later.
+            _ => {
+                sync.resume.remove(likes.key());
+
+                match newest {
+                    Some(marker) if args.filter.is_empty() => {
+                        sync.newest.insert(likes.key().to_string(), marker);
                     }
+                    _ => {}
                 }
-
-                trail_content = trail_content.replace("{{content}}", "");
-                body = trail_content;
             }
-
-            card = card.replace("{{body}}", &body);
-            posts_html = format!("{}{}", posts_html, card);
         }
 
-        bar.inc(1);
+        sync.save(&args.download.directory)
+            .with_context(|| "Could not save the sync state")?;
     }
 
-    // Write to html file
-    let out = HTML_TEMPLATE.replace("{{cards}}", &posts_html);
-
-    let path = Path::new(&file);
-    let display = path.display();
+    if let Some((_, reset)) = stopped_at {
+        return Err(Error::Api(format!(
+            "Used up today's API requests. Your progress is saved, run the same command \
+            again in {} to continue, or pass --wait-on-limit to wait instead.",
+            minutes(reset)
+        )));
+    }
 
-    let mut file = match File::create(&path) {
-        Ok(f) => f,
-        Err(e) => panic!("Couldn't create file {}: {}", display, e),
-    };
+    bar.finish();
 
-    match file.write_all(out.as_bytes()) {
-        Ok(_) => {
-            if verbose {
-                println!("Exported liked posts to {}.", display)
-            }
-        }
-        Err(e) => panic!("Couldn't write to {}: {}", display, e),
-    }
+    Ok(())
 }
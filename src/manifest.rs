@@ -0,0 +1,144 @@
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::Result;
+use crate::storage::Storage;
+
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// A record of every file downloaded into an output directory, keyed by post ID.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub posts: BTreeMap<u64, ArchivedPost>,
+    #[serde(skip)]
+    directory: PathBuf,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedPost {
+    pub blog_name: String,
+    pub media: Vec<ArchivedMedia>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedMedia {
+    pub url: String,
+    /// Relative to the output directory.
+    pub path: PathBuf,
+    pub size: u64,
+    /// Unix timestamp of when the file was downloaded.
+    pub downloaded_at: u64,
+}
+
+impl Manifest {
+    /// Loads the manifest from `directory`, or an empty one if there is none yet.
+    pub fn load(directory: &str) -> io::Result<Manifest> {
+        let directory = PathBuf::from(directory);
+
+        let mut manifest: Manifest = match File::open(directory.join(MANIFEST_FILE)) {
+            Ok(f) => serde_json::from_reader(BufReader::new(f))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Manifest::default(),
+            Err(e) => return Err(e),
+        };
+
+        manifest.directory = directory;
+        Ok(manifest)
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = self.directory.join(MANIFEST_FILE);
+        let tmp = path.with_extension("json.tmp");
+
+        fs::create_dir_all(&self.directory)?;
+
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        fs::rename(tmp, path)
+    }
+
+    /// Where a previously downloaded file lives on this machine, if it is still
+    /// there and intact.
+    pub fn local_path(&self, id: u64, url: &str) -> Option<PathBuf> {
+        let media = self.archived(id, url)?;
+
+        if self.is_intact(media) {
+            Some(self.directory.join(&media.path))
+        } else {
+            None
+        }
+    }
+
+    /// What the manifest knows about one of post `id`'s files.
+    pub fn archived(&self, id: u64, url: &str) -> Option<&ArchivedMedia> {
+        self.posts.get(&id)?.media.iter().find(|m| m.url == url)
+    }
+
+    /// Records a file stored as `path`, relative to the output directory,
+    /// replacing any earlier record of the same URL.
+    pub fn record(&mut self, id: u64, blog_name: &str, url: &str, path: &Path, size: u64) {
+        let downloaded_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        let post = self.posts.entry(id).or_insert_with(|| ArchivedPost {
+            blog_name: blog_name.to_string(),
+            media: Vec::new(),
+        });
+
+        post.media.retain(|m| m.url != url);
+        post.media.push(ArchivedMedia {
+            url: url.to_string(),
+            path: path.to_path_buf(),
+            size,
+            downloaded_at,
+        });
+    }
+
+    /// Drops every record whose file is missing from `storage` or has the
+    /// wrong size, so it gets downloaded again. Files of the wrong size are
+    /// removed from storage, so they aren't mistaken for finished downloads.
+    /// Returns the dropped records.
+    pub async fn verify(&mut self, storage: &dyn Storage) -> Result<Vec<ArchivedMedia>> {
+        let mut broken = Vec::new();
+
+        for post in self.posts.values_mut() {
+            let mut intact = Vec::new();
+
+            for media in post.media.drain(..) {
+                let key = media.path.to_string_lossy().to_string();
+
+                match storage.stat(&key).await? {
+                    Some(size) if size == media.size => intact.push(media),
+                    Some(_) => {
+                        storage.remove(&key).await?;
+                        broken.push(media);
+                    }
+                    None => broken.push(media),
+                }
+            }
+
+            post.media = intact;
+        }
+
+        self.posts.retain(|_, p| !p.media.is_empty());
+
+        Ok(broken)
+    }
+
+    fn is_intact(&self, media: &ArchivedMedia) -> bool {
+        fs::metadata(self.directory.join(&media.path)).is_ok_and(|m| m.len() == media.size)
+    }
+}
+
+impl ArchivedMedia {
+    /// Whether `storage` still has this file, at the size it was downloaded at.
+    pub async fn is_stored(&self, storage: &dyn Storage) -> bool {
+        let key = self.path.to_string_lossy();
+
+        matches!(storage.stat(&key).await, Ok(Some(size)) if size == self.size)
+    }
+}
@@ -0,0 +1,432 @@
+use std::collections::HashMap;
+
+use crate::photo_size::PhotoSize;
+use crate::types::{ContentBlock, Formatting, LayoutBlock, MediaObject, Post};
+
+/// The largest rendition of a piece of media.
+fn largest(media: &[MediaObject]) -> Option<&MediaObject> {
+    media.iter().max_by_key(|m| m.width.unwrap_or(0))
+}
+
+/// Finds the downloadable media in content blocks, along with the folder each belongs in.
+pub fn media(blocks: &[ContentBlock], size: &PhotoSize) -> Vec<(&'static str, String)> {
+    let mut media = Vec::new();
+
+    for block in blocks {
+        match block {
+            ContentBlock::Image { media: sizes, .. } => {
+                let sizes = sizes.iter().map(|m| (m.width.unwrap_or(0), m.url.as_str()));
+
+                if let Some(url) = size.pick(sizes) {
+                    media.push(("pics", url.to_string()));
+                }
+            }
+            // Videos from other sites, like YouTube, only have a link
+            ContentBlock::Video { media: Some(m), .. } => {
+                media.push(("videos", m.url.clone()));
+            }
+            ContentBlock::Audio {
+                media: file,
+                poster,
+                ..
+            } => {
+                if let Some(m) = file {
+                    media.push(("audio", m.url.clone()));
+                }
+
+                if let Some(m) = poster.as_deref().and_then(largest) {
+                    media.push(("audio", m.url.clone()));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    media
+}
+
+/// Finds the media in a post's content blocks and in those of its reblog trail.
+pub fn post_media(post: &Post, size: &PhotoSize) -> Vec<(&'static str, String)> {
+    let mut found = Vec::new();
+
+    if let Some(trail) = &post.trail {
+        for item in trail {
+            if let Some(content) = &item.content {
+                found.extend(media(content, size));
+            }
+        }
+    }
+
+    if let Some(content) = &post.content {
+        found.extend(media(content, size));
+    }
+
+    found
+}
+
+/// Renders content blocks as HTML, in the order given by the layout. `local`
+/// maps media URLs to where they were saved; media missing from it is
+/// reported as unavailable.
+pub fn render(
+    blocks: &[ContentBlock],
+    layout: Option<&[LayoutBlock]>,
+    local: &HashMap<String, String>,
+) -> String {
+    let rows = layout
+        .unwrap_or_default()
+        .iter()
+        .find(|l| l.kind == "rows")
+        .and_then(|l| l.display.as_ref());
+
+    let rows: Vec<Vec<usize>> = match rows {
+        Some(rows) => rows.iter().map(|r| r.blocks.clone()).collect(),
+        None => (0..blocks.len()).map(|i| vec![i]).collect(),
+    };
+
+    let mut html = String::new();
+    let mut rendered = vec![false; blocks.len()];
+
+    for row in rows {
+        let row: Vec<usize> = row.into_iter().filter(|&i| i < blocks.len()).collect();
+
+        for &i in &row {
+            rendered[i] = true;
+        }
+
+        // Blocks sharing a row, like a photoset, go side by side
+        if row.len() > 1 {
+            html.push_str("<div class='columns'>");
+
+            for i in row {
+                html.push_str(&format!(
+                    "<div class='column'>{}</div>",
+                    render_block(&blocks[i], local)
+                ));
+            }
+
+            html.push_str("</div>");
+        } else if let Some(&i) = row.first() {
+            html.push_str(&render_block(&blocks[i], local));
+        }
+    }
+
+    // Anything the layout left out, like the question of an ask
+    for (i, block) in blocks.iter().enumerate() {
+        if !rendered[i] {
+            html.push_str(&render_block(block, local));
+        }
+    }
+
+    html
+}
+
+fn render_block(block: &ContentBlock, local: &HashMap<String, String>) -> String {
+    match block {
+        ContentBlock::Text {
+            text,
+            subtype,
+            formatting,
+        } => {
+            let text = format_text(text, formatting.as_deref().unwrap_or_default());
+
+            match subtype.as_deref() {
+                Some("heading1") => format!("<h1>{}</h1>", text),
+                Some("heading2") => format!("<h2>{}</h2>", text),
+                Some("quote") | Some("indented") => format!("<blockquote>{}</blockquote>", text),
+                Some("ordered-list-item") => format!("<ol><li>{}</li></ol>", text),
+                Some("unordered-list-item") => format!("<ul><li>{}</li></ul>", text),
+                _ => format!("<p>{}</p>", text),
+            }
+        }
+        ContentBlock::Image { media, alt_text } => {
+            // Whichever size was downloaded
+            match media.iter().find_map(|m| local.get(&m.url)) {
+                Some(src) => format!(
+                    "<figure><img src='{}' alt='{}' /></figure>",
+                    escape(src),
+                    escape(alt_text.as_deref().unwrap_or_default())
+                ),
+                None => "<p>Could not fetch photo</p>".to_string(),
+            }
+        }
+        ContentBlock::Video { url, media, .. } => {
+            match media.as_ref().and_then(|m| local.get(&m.url)) {
+                Some(src) => format!(
+                    "<p><figure><video controls='controls' autoplay='autoplay' \
+                     muted='muted'><source src='{}'></video></figure></p>",
+                    escape(src)
+                ),
+                None => match url {
+                    Some(url) => format!("<p><a href='{}'>{}</a></p>", escape(url), escape(url)),
+                    None => "<p>Could not fetch video</p>".to_string(),
+                },
+            }
+        }
+        ContentBlock::Audio {
+            url,
+            media,
+            title,
+            artist,
+            ..
+        } => {
+            let name = match (title, artist) {
+                (Some(t), Some(a)) => format!("{} - {}", a, t),
+                (Some(t), None) => t.clone(),
+                _ => "Audio".to_string(),
+            };
+
+            match media.as_ref().and_then(|m| local.get(&m.url)) {
+                Some(src) => format!(
+                    "<p>{}</p><p><audio controls='controls' src='{}'></audio></p>",
+                    escape(&name),
+                    escape(src)
+                ),
+                None => match url {
+                    Some(url) => format!("<p><a href='{}'>{}</a></p>", escape(url), escape(&name)),
+                    None => format!("<p>{}</p>", escape(&name)),
+                },
+            }
+        }
+        ContentBlock::Link {
+            url,
+            title,
+            description,
+            ..
+        } => {
+            let mut html = format!(
+                "<p><a href='{}'>{}</a></p>",
+                escape(url),
+                escape(title.as_deref().unwrap_or(url))
+            );
+
+            if let Some(description) = description {
+                html.push_str(&format!("<p>{}</p>", escape(description)));
+            }
+
+            html
+        }
+        ContentBlock::Unknown => String::new(),
+    }
+}
+
+/// Escapes `text` and applies its formatting ranges, which count characters.
+/// Ranges may overlap, but the tags they become are always nested.
+fn format_text(text: &str, formatting: &[Formatting]) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let end = |f: &Formatting| f.end.min(chars.len());
+
+    let mut html = String::new();
+    let mut open: Vec<&Formatting> = Vec::new();
+
+    for i in 0..=chars.len() {
+        // Tags opened since one that ends here are closed along with it, and
+        // opened again after
+        let mut reopen = Vec::new();
+
+        while open.iter().any(|f| end(f) == i) {
+            if let Some(f) = open.pop() {
+                html.push_str(close_tag(f));
+
+                if end(f) != i {
+                    reopen.push(f);
+                }
+            }
+        }
+
+        // Longer ranges open first, so they close last
+        let mut starting: Vec<&Formatting> = formatting
+            .iter()
+            .filter(|f| f.start == i && f.start < end(f))
+            .collect();
+        starting.sort_by_key(|f| std::cmp::Reverse(end(f)));
+
+        for f in reopen.into_iter().rev().chain(starting) {
+            html.push_str(&open_tag(f));
+            open.push(f);
+        }
+
+        if let Some(c) = chars.get(i) {
+            html.push_str(&escape(&c.to_string()));
+        }
+    }
+
+    html
+}
+
+fn open_tag(f: &Formatting) -> String {
+    match f.kind.as_str() {
+        "bold" => "<b>".to_string(),
+        "italic" => "<i>".to_string(),
+        "strikethrough" => "<s>".to_string(),
+        "small" => "<small>".to_string(),
+        "link" => format!(
+            "<a href='{}'>",
+            escape(f.url.as_deref().unwrap_or_default())
+        ),
+        "mention" => match &f.blog {
+            Some(blog) => format!("<a href='https://{}.tumblr.com/'>", escape(&blog.name)),
+            None => "<a>".to_string(),
+        },
+        "color" => format!(
+            "<span style='color: {}'>",
+            escape(f.hex.as_deref().unwrap_or_default())
+        ),
+        _ => "<span>".to_string(),
+    }
+}
+
+fn close_tag(f: &Formatting) -> &'static str {
+    match f.kind.as_str() {
+        "bold" => "</b>",
+        "italic" => "</i>",
+        "strikethrough" => "</s>",
+        "small" => "</small>",
+        "link" | "mention" => "</a>",
+        _ => "</span>",
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('\'', "&#39;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn blocks(value: Value) -> Vec<ContentBlock> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn ranges(value: Value) -> Vec<Formatting> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn text(text: &str) -> Value {
+        json!({ "type": "text", "text": text })
+    }
+
+    #[test]
+    fn nests_formatting_ranges() {
+        let formatting = ranges(json!([
+            { "start": 0, "end": 3, "type": "bold" },
+            { "start": 0, "end": 6, "type": "italic" },
+        ]));
+
+        assert_eq!(format_text("abcdef", &formatting), "<i><b>abc</b>def</i>");
+    }
+
+    #[test]
+    fn splits_overlapping_ranges() {
+        let formatting = ranges(json!([
+            { "start": 0, "end": 4, "type": "bold" },
+            { "start": 2, "end": 6, "type": "italic" },
+        ]));
+
+        assert_eq!(
+            format_text("abcdef", &formatting),
+            "<b>ab<i>cd</i></b><i>ef</i>"
+        );
+    }
+
+    #[test]
+    fn counts_characters_and_clamps_ranges() {
+        let formatting = ranges(json!([
+            { "start": 1, "end": 2, "type": "small" },
+            { "start": 2, "end": 99, "type": "strikethrough" },
+            { "start": 1, "end": 1, "type": "bold" },
+        ]));
+
+        assert_eq!(
+            format_text("héllo", &formatting),
+            "h<small>é</small><s>llo</s>"
+        );
+    }
+
+    #[test]
+    fn escapes_text_and_links() {
+        let formatting = ranges(json!([
+            { "start": 0, "end": 5, "type": "link", "url": "https://a.com/?q='x'&y" },
+        ]));
+
+        assert_eq!(
+            format_text("<a&b>", &formatting),
+            "<a href='https://a.com/?q=&#39;x&#39;&amp;y'>&lt;a&amp;b&gt;</a>"
+        );
+    }
+
+    #[test]
+    fn puts_blocks_sharing_a_row_side_by_side() {
+        let content = blocks(json!([text("a"), text("b"), text("c")]));
+        let layout: Vec<LayoutBlock> = serde_json::from_value(json!([
+            { "type": "rows", "display": [{ "blocks": [2] }, { "blocks": [0, 1, 7] }] },
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            render(&content, Some(&layout), &HashMap::new()),
+            "<p>c</p><div class='columns'><div class='column'><p>a</p></div>\
+             <div class='column'><p>b</p></div></div>"
+        );
+    }
+
+    #[test]
+    fn renders_blocks_the_layout_leaves_out() {
+        let content = blocks(json!([text("question"), text("answer")]));
+        let layout: Vec<LayoutBlock> = serde_json::from_value(json!([
+            { "type": "rows", "display": [{ "blocks": [1] }] },
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            render(&content, Some(&layout), &HashMap::new()),
+            "<p>answer</p><p>question</p>"
+        );
+    }
+
+    #[test]
+    fn links_to_media_from_other_sites() {
+        let content = blocks(json!([
+            { "type": "video", "url": "https://youtube.com/watch?v=1&t=2", "provider": "youtube" },
+            {
+                "type": "audio",
+                "url": "https://open.spotify.com/track/1",
+                "provider": "spotify",
+                "title": "Song",
+                "artist": "<Band>",
+            },
+        ]));
+
+        assert_eq!(
+            render(&content, None, &HashMap::new()),
+            "<p><a href='https://youtube.com/watch?v=1&amp;t=2'>\
+             https://youtube.com/watch?v=1&amp;t=2</a></p>\
+             <p><a href='https://open.spotify.com/track/1'>&lt;Band&gt; - Song</a></p>"
+        );
+        assert!(media(&content, &PhotoSize::Largest).is_empty());
+    }
+
+    #[test]
+    fn uses_downloaded_media() {
+        let content = blocks(json!([{
+            "type": "video",
+            "url": "https://tumblr.com/video",
+            "media": { "url": "https://va.media.tumblr.com/v.mp4" },
+        }]));
+        let local = HashMap::from([(
+            "https://va.media.tumblr.com/v.mp4".to_string(),
+            "videos/v.mp4".to_string(),
+        )]);
+
+        assert_eq!(
+            media(&content, &PhotoSize::Largest),
+            [("videos", "https://va.media.tumblr.com/v.mp4".to_string())]
+        );
+        assert!(render(&content, None, &local).contains("<source src='videos/v.mp4'>"));
+    }
+}
@@ -0,0 +1,288 @@
+use hmac::{Hmac, Mac};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use reqwest::header::AUTHORIZATION;
+use reqwest::{Method, RequestBuilder, Url};
+use sha1::Sha1;
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+use crate::error::{Context, Error, Result};
+
+const REQUEST_TOKEN_URL: &str = "https://www.tumblr.com/oauth/request_token";
+const AUTHORIZE_URL: &str = "https://www.tumblr.com/oauth/authorize";
+const ACCESS_TOKEN_URL: &str = "https://www.tumblr.com/oauth/access_token";
+
+/// Everything except the unreserved characters of RFC 3986, as OAuth requires.
+const OAUTH_ENCODE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// OAuth 1.0a credentials. The consumer key is the same as the API key.
+#[derive(Debug, Clone)]
+pub struct OAuthCredentials {
+    pub consumer_key: String,
+    pub consumer_secret: String,
+    pub token: String,
+    pub token_secret: String,
+}
+
+impl OAuthCredentials {
+    /// Builds a GET request for `url`, signed with these credentials.
+    pub fn get(&self, client: &reqwest::Client, url: &str) -> RequestBuilder {
+        let parsed = match Url::parse(url) {
+            Ok(parsed) => parsed,
+            // Can't be signed, and reqwest reports the URL when it's sent
+            Err(_) => return client.get(url),
+        };
+
+        let header = authorization(
+            &self.consumer_key,
+            &self.consumer_secret,
+            Some((&self.token, &self.token_secret)),
+            &Method::GET,
+            &parsed,
+            &[],
+        );
+
+        client.get(parsed).header(AUTHORIZATION, header)
+    }
+}
+
+/// Runs the three-legged OAuth flow: hands `open` the link the user needs to
+/// open in their browser to allow access, and catches the redirect back on a
+/// local port. Returns the access token and its secret.
+pub async fn authorize(
+    client: &reqwest::Client,
+    consumer_key: &str,
+    consumer_secret: &str,
+    open: impl FnOnce(&str),
+) -> Result<(String, String)> {
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .with_context(|| "Could not listen for the authorization callback")?;
+    let callback = format!(
+        "http://{}/callback",
+        listener
+            .local_addr()
+            .with_context(|| "Could not listen for the authorization callback")?
+    );
+
+    // Get a temporary request token
+    let header = authorization(
+        consumer_key,
+        consumer_secret,
+        None,
+        &Method::POST,
+        &endpoint(REQUEST_TOKEN_URL)?,
+        &[("oauth_callback", &callback)],
+    );
+    let request = post_form(client, REQUEST_TOKEN_URL, header)
+        .await
+        .with_context(|| "Could not get a request token")?;
+    let token = form_value(&request, "oauth_token")?;
+    let token_secret = form_value(&request, "oauth_token_secret")?;
+
+    open(&format!("{}?oauth_token={}", AUTHORIZE_URL, token));
+
+    // Wait for Tumblr to send the browser back to us
+    let callback = wait_for_callback(&listener).await?;
+
+    if form_value(&callback, "oauth_token")? != token {
+        return Err(Error::Transfer(
+            "The authorization callback was for a different request".to_string(),
+        ));
+    }
+
+    let verifier = form_value(&callback, "oauth_verifier")?;
+
+    // Trade the request token for an access token
+    let header = authorization(
+        consumer_key,
+        consumer_secret,
+        Some((&token, &token_secret)),
+        &Method::POST,
+        &endpoint(ACCESS_TOKEN_URL)?,
+        &[("oauth_verifier", &verifier)],
+    );
+    let access = post_form(client, ACCESS_TOKEN_URL, header)
+        .await
+        .with_context(|| "Could not get an access token")?;
+
+    Ok((
+        form_value(&access, "oauth_token")?,
+        form_value(&access, "oauth_token_secret")?,
+    ))
+}
+
+fn endpoint(url: &str) -> Result<Url> {
+    Url::parse(url).map_err(|e| Error::Config(format!("\"{}\" is not a valid URL: {}", url, e)))
+}
+
+/// Sends a signed POST and reads the form encoded reply.
+async fn post_form(
+    client: &reqwest::Client,
+    url: &str,
+    header: String,
+) -> std::result::Result<HashMap<String, String>, reqwest::Error> {
+    let res = client
+        .post(url)
+        .header(AUTHORIZATION, header)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    Ok(parse_form(&res.text().await?))
+}
+
+/// Accepts connections until one of them is the OAuth callback, and returns its query.
+async fn wait_for_callback(listener: &TcpListener) -> Result<HashMap<String, String>> {
+    let context = || "Could not receive the authorization callback";
+
+    loop {
+        let (mut socket, _) = listener.accept().await.with_context(context)?;
+        let mut buf = vec![0; 8192];
+        let read = socket.read(&mut buf).await.with_context(context)?;
+        let request = String::from_utf8_lossy(&buf[..read]);
+
+        // The request line looks like "GET /callback?oauth_token=...&oauth_verifier=... HTTP/1.1"
+        let target = request.split_whitespace().nth(1).unwrap_or_default();
+
+        let query = match target.strip_prefix("/callback?") {
+            Some(q) => q,
+            None => {
+                socket
+                    .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n")
+                    .await
+                    .with_context(context)?;
+                continue;
+            }
+        };
+
+        let body = "Authorized! You can close this window.";
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        socket
+            .write_all(response.as_bytes())
+            .await
+            .with_context(context)?;
+
+        return Ok(parse_form(query));
+    }
+}
+
+/// Builds the `Authorization` header for a request signed with HMAC-SHA1.
+/// Query parameters in `url` are signed along with `extra` OAuth parameters.
+fn authorization(
+    consumer_key: &str,
+    consumer_secret: &str,
+    token: Option<(&str, &str)>,
+    method: &Method,
+    url: &Url,
+    extra: &[(&str, &str)],
+) -> String {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+        .to_string();
+    let nonce: String = std::iter::repeat_with(fastrand::alphanumeric)
+        .take(32)
+        .collect();
+
+    let mut oauth: Vec<(String, String)> = vec![
+        ("oauth_consumer_key".into(), consumer_key.into()),
+        ("oauth_nonce".into(), nonce),
+        ("oauth_signature_method".into(), "HMAC-SHA1".into()),
+        ("oauth_timestamp".into(), timestamp),
+        ("oauth_version".into(), "1.0".into()),
+    ];
+
+    if let Some((token, _)) = token {
+        oauth.push(("oauth_token".into(), token.into()));
+    }
+
+    for (k, v) in extra {
+        oauth.push((k.to_string(), v.to_string()));
+    }
+
+    let signature = signature(
+        method,
+        url,
+        &oauth,
+        consumer_secret,
+        token.map(|(_, secret)| secret).unwrap_or_default(),
+    );
+
+    oauth.push(("oauth_signature".into(), signature));
+
+    let fields = oauth
+        .iter()
+        .map(|(k, v)| format!("{}=\"{}\"", encode(k), encode(v)))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    format!("OAuth {}", fields)
+}
+
+/// The HMAC-SHA1 signature of a request, as in section 3.4 of RFC 5849. It
+/// covers the `oauth` parameters along with those in `url`'s query string.
+pub fn signature(
+    method: &Method,
+    url: &Url,
+    oauth: &[(String, String)],
+    consumer_secret: &str,
+    token_secret: &str,
+) -> String {
+    // The signature covers the OAuth parameters and the query string, sorted
+    let mut params: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(k, v)| (encode(&k), encode(&v)))
+        .chain(oauth.iter().map(|(k, v)| (encode(k), encode(v))))
+        .collect();
+    params.sort();
+
+    let params = params
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<_>>()
+        .join("&");
+
+    let mut base_url = url.clone();
+    base_url.set_query(None);
+    base_url.set_fragment(None);
+
+    let base = format!(
+        "{}&{}&{}",
+        method.as_str(),
+        encode(base_url.as_str()),
+        encode(&params)
+    );
+    let key = format!("{}&{}", encode(consumer_secret), encode(token_secret));
+
+    let mut mac = Hmac::<Sha1>::new_from_slice(key.as_bytes()).expect("HMAC takes any key size");
+    mac.update(base.as_bytes());
+    base64::encode(mac.finalize().into_bytes())
+}
+
+fn encode(s: &str) -> String {
+    utf8_percent_encode(s, OAUTH_ENCODE).to_string()
+}
+
+fn parse_form(s: &str) -> HashMap<String, String> {
+    url::form_urlencoded::parse(s.as_bytes())
+        .into_owned()
+        .collect()
+}
+
+fn form_value(form: &HashMap<String, String>, key: &str) -> Result<String> {
+    form.get(key)
+        .cloned()
+        .ok_or_else(|| Error::Transfer(format!("Tumblr did not send an {}", key)))
+}
@@ -0,0 +1,51 @@
+use std::str::FromStr;
+
+/// Which of a photo's sizes to download.
+#[derive(Debug, Clone, Copy)]
+pub enum PhotoSize {
+    Largest,
+    /// The smallest size at least this wide, or the largest if none are.
+    Above(u32),
+    /// Exactly this wide, or the largest if there is no such size.
+    Width(u32),
+}
+
+impl FromStr for PhotoSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<PhotoSize, String> {
+        let parse_width = |w: &str| {
+            w.parse::<u32>()
+                .map_err(|_| format!("\"{}\" is not a width in pixels", w))
+        };
+
+        match s.split_once(':') {
+            None if s == "largest" => Ok(PhotoSize::Largest),
+            Some(("above", w)) => Ok(PhotoSize::Above(parse_width(w)?)),
+            Some(("width", w)) => Ok(PhotoSize::Width(parse_width(w)?)),
+            _ => Err("expected largest, above:<width> or width:<width>".to_string()),
+        }
+    }
+}
+
+impl PhotoSize {
+    /// Picks one of several sizes of the same photo, given as `(width, url)` pairs.
+    pub fn pick<'a, I>(&self, sizes: I) -> Option<&'a str>
+    where
+        I: IntoIterator<Item = (u32, &'a str)>,
+    {
+        let sizes: Vec<(u32, &str)> = sizes.into_iter().collect();
+        let largest = sizes.iter().max_by_key(|(w, _)| *w);
+
+        let picked = match self {
+            PhotoSize::Largest => None,
+            PhotoSize::Above(min) => sizes
+                .iter()
+                .filter(|(w, _)| w >= min)
+                .min_by_key(|(w, _)| *w),
+            PhotoSize::Width(width) => sizes.iter().find(|(w, _)| w == width),
+        };
+
+        picked.or(largest).map(|(_, url)| *url)
+    }
+}
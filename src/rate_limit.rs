@@ -0,0 +1,84 @@
+use reqwest::header::HeaderMap;
+use std::time::Duration;
+
+/// How many requests are left in one of the API's rate limit windows.
+#[derive(Debug, Clone, Copy)]
+struct Quota {
+    limit: u64,
+    remaining: u64,
+    /// Time until the window starts over.
+    reset: Duration,
+}
+
+/// The rate limits an API response reported, from its `X-Ratelimit-*` headers.
+#[derive(Debug, Default)]
+pub struct RateLimit {
+    per_hour: Option<Quota>,
+    per_day: Option<Quota>,
+}
+
+/// What to do before sending the next request.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Throttle {
+    Go,
+    Wait(Duration),
+    /// Nothing more can be sent until the daily quota resets, in this long.
+    DailyQuotaUsed(Duration),
+}
+
+/// Once fewer than this fraction of the hourly requests are left, the rest are
+/// spread out over what is left of the hour.
+const SLOW_DOWN_BELOW: u64 = 10;
+
+impl RateLimit {
+    pub fn from_headers(headers: &HeaderMap) -> RateLimit {
+        RateLimit {
+            per_hour: quota(headers, "perhour"),
+            per_day: quota(headers, "perday"),
+        }
+    }
+
+    pub fn throttle(&self) -> Throttle {
+        if let Some(day) = self.per_day {
+            if day.remaining == 0 {
+                return Throttle::DailyQuotaUsed(day.reset);
+            }
+        }
+
+        match self.per_hour {
+            Some(hour) if hour.remaining == 0 => Throttle::Wait(hour.reset),
+            Some(hour) if hour.remaining < hour.limit / SLOW_DOWN_BELOW => {
+                Throttle::Wait(hour.reset / (hour.remaining as u32 + 1))
+            }
+            _ => Throttle::Go,
+        }
+    }
+}
+
+/// Reads one window, e.g. `X-Ratelimit-Perhour-Remaining` for `perhour`. The
+/// reset header counts seconds.
+fn quota(headers: &HeaderMap, window: &str) -> Option<Quota> {
+    let value = |field: &str| -> Option<u64> {
+        headers
+            .get(format!("x-ratelimit-{}-{}", window, field))?
+            .to_str()
+            .ok()?
+            .trim()
+            .parse()
+            .ok()
+    };
+
+    Some(Quota {
+        limit: value("limit")?,
+        remaining: value("remaining")?,
+        reset: Duration::from_secs(value("reset").unwrap_or(0)),
+    })
+}
+
+/// A wait rounded up to whole minutes, e.g. "5 minutes".
+pub fn minutes(wait: Duration) -> String {
+    match wait.as_secs().div_ceil(60).max(1) {
+        1 => "1 minute".to_string(),
+        n => format!("{} minutes", n),
+    }
+}
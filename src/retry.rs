@@ -0,0 +1,85 @@
+use reqwest::header::RETRY_AFTER;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use std::time::{Duration, SystemTime};
+
+/// The longest we will back off between two attempts, unless the server asks for more.
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one.
+    pub max_attempts: u32,
+    /// Delay before the first retry. Doubles with every further attempt.
+    pub base_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    /// Six attempts, starting a second apart.
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 6,
+            base_delay: Duration::from_secs(1),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Sends the request built by `build`, retrying connection errors, timeouts,
+    /// `429 Too Many Requests` and `5xx` responses.
+    ///
+    /// If the final attempt still fails with a retryable status, that response is
+    /// returned as-is so the caller can report it.
+    pub async fn send<F>(&self, build: F) -> Result<Response, reqwest::Error>
+    where
+        F: Fn() -> RequestBuilder,
+    {
+        let mut attempt = 1;
+
+        loop {
+            let last = attempt >= self.max_attempts;
+
+            let delay = match build().send().await {
+                Ok(res) if !last && is_retryable(res.status()) => {
+                    retry_after(&res).unwrap_or_else(|| self.backoff(attempt))
+                }
+                Err(e) if !last && is_transient(&e) => self.backoff(attempt),
+                res => return res,
+            };
+
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// Exponential backoff with jitter: somewhere between half and all of
+    /// `base_delay * 2^(attempt - 1)`, capped at `MAX_DELAY`.
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        let exp = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt - 1))
+            .min(MAX_DELAY);
+
+        let half = exp.as_millis() as u64 / 2;
+        Duration::from_millis(half + fastrand::u64(0..=half))
+    }
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+fn is_transient(e: &reqwest::Error) -> bool {
+    e.is_timeout() || e.is_connect() || e.is_request() || e.is_body()
+}
+
+/// Reads a `Retry-After` header, given either in seconds or as an HTTP date.
+fn retry_after(res: &Response) -> Option<Duration> {
+    let value = res.headers().get(RETRY_AFTER)?.to_str().ok()?;
+
+    if let Ok(secs) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(date.duration_since(SystemTime::now()).unwrap_or_default())
+}
@@ -0,0 +1,240 @@
+use async_trait::async_trait;
+use futures::stream::{self, TryStreamExt};
+use hmac::{Hmac, Mac};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use reqwest::header::{AUTHORIZATION, CONTENT_LENGTH};
+use reqwest::{Body, Method, RequestBuilder, StatusCode};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio_util::io::ReaderStream;
+use url::{Position, Url};
+
+use crate::client::{check_host, Client};
+use crate::error::{Context, Result};
+use crate::retry::RetryPolicy;
+use crate::storage::Storage;
+
+/// Everything except the unreserved characters and slashes, as S3 expects in
+/// object keys.
+const KEY_ENCODE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~')
+    .remove(b'/');
+
+/// Where to reach a bucket, and the credentials to use it with.
+#[derive(Debug, Clone)]
+pub struct S3Options {
+    /// A base URL like `https://s3.us-east-1.amazonaws.com` or
+    /// `http://localhost:9000`, without a trailing slash.
+    pub endpoint: String,
+    pub bucket: String,
+    pub region: String,
+    /// Put in front of every key, e.g. `likes/`.
+    pub prefix: String,
+    pub access_key: String,
+    pub secret_key: String,
+}
+
+/// Keeps files in an S3 bucket, or anything that speaks the same API, like
+/// MinIO. Buckets are addressed by path, which every such server supports.
+#[derive(Debug, Clone)]
+pub struct S3Storage {
+    http: reqwest::Client,
+    retry: RetryPolicy,
+    options: S3Options,
+}
+
+impl S3Storage {
+    /// Fails with `Error::Config` if the endpoint isn't a base URL.
+    pub fn new(client: &Client, options: S3Options) -> Result<S3Storage> {
+        check_host(&options.endpoint)?;
+
+        Ok(S3Storage {
+            http: client.http().clone(),
+            retry: client.options().retry.clone(),
+            options,
+        })
+    }
+
+    fn url(&self, key: &str) -> String {
+        let options = &self.options;
+
+        format!(
+            "{}/{}/{}",
+            options.endpoint,
+            options.bucket,
+            utf8_percent_encode(&format!("{}{}", options.prefix, key), KEY_ENCODE)
+        )
+    }
+
+    /// Builds a request signed with AWS Signature Version 4. `payload` is the
+    /// SHA-256 hash of the body, or `UNSIGNED-PAYLOAD` to leave it out.
+    fn request(&self, method: Method, url: &str, payload: &str) -> RequestBuilder {
+        let options = &self.options;
+
+        let (date, time) = utc_now();
+        let timestamp = format!("{}T{}Z", date, time);
+        let scope = format!("{}/{}/s3/aws4_request", date, options.region);
+
+        let parsed = match Url::parse(url) {
+            Ok(parsed) => parsed,
+            // Can't be signed, and reqwest reports the URL when it's sent
+            Err(_) => return self.http.request(method, url),
+        };
+        let host = &parsed[Position::BeforeHost..Position::AfterPort];
+
+        let canonical = format!(
+            "{}\n{}\n\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+            method.as_str(),
+            parsed.path(),
+            host,
+            payload,
+            timestamp,
+            SIGNED_HEADERS,
+            payload
+        );
+        let to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            timestamp,
+            scope,
+            hex(&Sha256::digest(canonical.as_bytes()))
+        );
+
+        // The key is derived from the secret, narrowed down to this scope
+        let key = [date.as_str(), options.region.as_str(), "s3", "aws4_request"]
+            .iter()
+            .fold(
+                format!("AWS4{}", options.secret_key).into_bytes(),
+                |key, part| hmac(&key, part.as_bytes()),
+            );
+        let signature = hex(&hmac(&key, to_sign.as_bytes()));
+
+        self.http
+            .request(method, url)
+            .header("x-amz-content-sha256", payload)
+            .header("x-amz-date", timestamp)
+            .header(
+                AUTHORIZATION,
+                format!(
+                    "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                    options.access_key, scope, SIGNED_HEADERS, signature
+                ),
+            )
+    }
+}
+
+const SIGNED_HEADERS: &str = "host;x-amz-content-sha256;x-amz-date";
+
+/// Files are streamed as they're uploaded, so their hash isn't known up front.
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+#[async_trait]
+impl Storage for S3Storage {
+    async fn put(&self, key: &str, file: &Path) -> Result<()> {
+        let len = fs::metadata(file)
+            .with_context(|| format!("Could not read {}", file.display()))?
+            .len();
+        let url = self.url(key);
+
+        // Each attempt reads the file again from the start
+        let body = || {
+            let reader = stream::once(tokio::fs::File::open(file.to_path_buf()))
+                .map_ok(ReaderStream::new)
+                .try_flatten();
+
+            Body::wrap_stream(reader)
+        };
+
+        self.retry
+            .send(|| {
+                self.request(Method::PUT, &url, UNSIGNED_PAYLOAD)
+                    .header(CONTENT_LENGTH, len)
+                    .body(body())
+            })
+            .await
+            .and_then(|res| res.error_for_status())
+            .with_context(|| format!("Could not upload {}", key))?;
+
+        // It's in the bucket now, so the local copy isn't needed
+        fs::remove_file(file).with_context(|| format!("Could not remove {}", file.display()))
+    }
+
+    async fn stat(&self, key: &str) -> Result<Option<u64>> {
+        let url = self.url(key);
+
+        let res = self
+            .retry
+            .send(|| self.request(Method::HEAD, &url, &hex(&Sha256::digest(b""))))
+            .await
+            .with_context(|| format!("Could not look up {}", key))?;
+
+        if res.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let res = res
+            .error_for_status()
+            .with_context(|| format!("Could not look up {}", key))?;
+
+        // A HEAD response has no body, so its length is only in the header
+        let size = res
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|len| len.to_str().ok()?.parse().ok())
+            .unwrap_or(0);
+
+        Ok(Some(size))
+    }
+
+    async fn remove(&self, key: &str) -> Result<()> {
+        let url = self.url(key);
+
+        // Deleting a key that doesn't exist succeeds too
+        self.retry
+            .send(|| self.request(Method::DELETE, &url, &hex(&Sha256::digest(b""))))
+            .await
+            .and_then(|res| res.error_for_status())
+            .with_context(|| format!("Could not remove {}", key))?;
+
+        Ok(())
+    }
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes any key size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// The current UTC date and time, as `YYYYMMDD` and `HHMMSS`.
+fn utc_now() -> (String, String) {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (days, secs) = ((secs / 86400) as i64, secs % 86400);
+
+    // From Howard Hinnant's civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+
+    (
+        format!("{:04}{:02}{:02}", y, m, d),
+        format!("{:02}{:02}{:02}", secs / 3600, secs % 3600 / 60, secs % 60),
+    )
+}
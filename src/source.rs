@@ -0,0 +1,135 @@
+use futures::stream::{self, Stream, TryStreamExt};
+use indicatif::ProgressBar;
+
+use crate::client::{Client, Likes, PAGE_SIZE};
+use crate::error::{Error, Result};
+use crate::rate_limit::Throttle;
+use crate::sync::Marker;
+use crate::types::Post;
+use crate::util::warn;
+
+/// Pages through liked posts, newest first. Failed requests are retried as the
+/// client's retry policy says, and requests are paced by the API's rate limits.
+#[derive(Debug, Clone)]
+pub struct LikesSource {
+    client: Client,
+    likes: Likes,
+    before: Option<String>,
+    stop_at: Option<Marker>,
+    wait_on_limit: bool,
+    bar: ProgressBar,
+}
+
+/// Where paging has got to.
+struct State {
+    source: LikesSource,
+    /// What the last page's rate limits say to do before the next request.
+    throttle: Throttle,
+    done: bool,
+}
+
+impl LikesSource {
+    pub fn new(client: &Client, likes: Likes) -> LikesSource {
+        LikesSource {
+            client: client.clone(),
+            likes,
+            before: None,
+            stop_at: None,
+            wait_on_limit: false,
+            bar: ProgressBar::hidden(),
+        }
+    }
+
+    /// Starts from a page cursor, like one saved from [`Error::DailyLimit`].
+    pub fn starting_before(mut self, cursor: String) -> LikesSource {
+        self.before = Some(cursor);
+        self
+    }
+
+    /// Ends the stream just before the marked post, or the first one liked
+    /// before it, e.g. the newest one archived by a previous run.
+    pub fn stopping_at(mut self, marker: Marker) -> LikesSource {
+        self.stop_at = Some(marker);
+        self
+    }
+
+    /// Waits for the daily quota to reset once it's used up, instead of
+    /// ending the stream with [`Error::DailyLimit`].
+    pub fn wait_on_limit(mut self, wait: bool) -> LikesSource {
+        self.wait_on_limit = wait;
+        self
+    }
+
+    /// Warns on `bar` about posts that could not be read, which are otherwise
+    /// skipped silently.
+    pub fn reporting_to(mut self, bar: &ProgressBar) -> LikesSource {
+        self.bar = bar.clone();
+        self
+    }
+
+    /// Every liked post, a page at a time. Ends after the last page, or with
+    /// an error if a page could not be fetched.
+    pub fn posts(self) -> impl Stream<Item = Result<Post>> {
+        self.pages()
+            .map_ok(|posts| stream::iter(posts.into_iter().map(Ok)))
+            .try_flatten()
+    }
+
+    /// Every page of liked posts.
+    pub fn pages(self) -> impl Stream<Item = Result<Vec<Post>>> {
+        let state = State {
+            source: self,
+            throttle: Throttle::Go,
+            done: false,
+        };
+
+        stream::try_unfold(state, |mut state| async move {
+            if state.done {
+                return Ok(None);
+            }
+
+            let source = &mut state.source;
+
+            match state.throttle {
+                Throttle::Go => {}
+                Throttle::Wait(delay) => tokio::time::sleep(delay).await,
+                Throttle::DailyQuotaUsed(reset) if source.wait_on_limit => {
+                    tokio::time::sleep(reset).await
+                }
+                Throttle::DailyQuotaUsed(reset) => {
+                    return Err(Error::DailyLimit {
+                        resume: source.before.clone().unwrap_or_default(),
+                        reset,
+                    });
+                }
+            }
+
+            let mut page = source
+                .client
+                .likes_page(&source.likes, PAGE_SIZE, source.before.as_deref())
+                .await?;
+
+            for message in page.skipped {
+                warn(&source.bar, message);
+            }
+
+            // Everything from here on was seen before
+            if let Some(marker) = source.stop_at {
+                if let Some(i) = page.posts.iter().position(|p| marker.reached(p)) {
+                    page.posts.truncate(i);
+                    state.done = true;
+                }
+            }
+
+            match page.next {
+                // An empty page would only lead back to itself
+                Some(next) if !page.posts.is_empty() => source.before = Some(next),
+                _ => state.done = true,
+            }
+
+            state.throttle = page.rate_limit.throttle();
+
+            Ok(Some((page.posts, state)))
+        })
+    }
+}
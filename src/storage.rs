@@ -0,0 +1,75 @@
+use async_trait::async_trait;
+use std::fmt::Debug;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::error::{Context, Result};
+
+/// Somewhere downloaded files are kept. Files are named by keys like
+/// `pics/1600000000_123_0.jpg`, relative to the storage's root.
+#[async_trait]
+pub trait Storage: Debug + Send + Sync {
+    /// Moves the finished local file `file` into storage as `key`, replacing
+    /// anything stored there before.
+    async fn put(&self, key: &str, file: &Path) -> Result<()>;
+
+    /// The size in bytes of what is stored as `key`, or `None` if nothing is.
+    async fn stat(&self, key: &str) -> Result<Option<u64>>;
+
+    /// Deletes what is stored as `key`, if anything is.
+    async fn remove(&self, key: &str) -> Result<()>;
+
+    async fn exists(&self, key: &str) -> Result<bool> {
+        Ok(self.stat(key).await?.is_some())
+    }
+}
+
+/// Keeps files in a directory on the local filesystem.
+#[derive(Debug, Clone)]
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new<P: Into<PathBuf>>(root: P) -> LocalStorage {
+        LocalStorage { root: root.into() }
+    }
+
+    /// Where `key` is kept.
+    pub fn path(&self, key: &str) -> PathBuf {
+        self.root.join(key)
+    }
+}
+
+#[async_trait]
+impl Storage for LocalStorage {
+    async fn put(&self, key: &str, file: &Path) -> Result<()> {
+        let path = self.path(key);
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Could not create directory {}", parent.display()))?;
+        }
+
+        fs::rename(file, &path)
+            .with_context(|| format!("Could not move {} into place", file.display()))
+    }
+
+    async fn stat(&self, key: &str) -> Result<Option<u64>> {
+        match fs::metadata(self.path(key)) {
+            Ok(m) => Ok(Some(m.len())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Could not read {}", key)),
+        }
+    }
+
+    async fn remove(&self, key: &str) -> Result<()> {
+        match fs::remove_file(self.path(key)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                Err(e).with_context(|| format!("Could not remove {}", key))
+            }
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::PathBuf;
+
+use crate::types::Post;
+
+const SYNC_FILE: &str = "sync.json";
+
+/// What previous runs have already archived, kept in the download directory.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SyncState {
+    /// The newest liked post downloaded, per blog.
+    pub newest: HashMap<String, Marker>,
+    /// Where a run stopped early, per blog, so the next one can pick up from there.
+    #[serde(default)]
+    pub resume: HashMap<String, Checkpoint>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// The page cursor to continue from.
+    pub before: String,
+    /// The newest like of the run that stopped, saved once the rest are downloaded.
+    pub newest: Option<Marker>,
+}
+
+/// A liked post an incremental run stops at, along with every older one.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(from = "SavedMarker")]
+pub struct Marker {
+    pub id: u64,
+    /// When the post was liked, or made if that isn't known. Unknown for
+    /// markers saved by older versions.
+    pub timestamp: Option<u64>,
+}
+
+impl Marker {
+    pub fn new(post: &Post) -> Marker {
+        Marker {
+            id: post.id,
+            timestamp: Some(post.liked_timestamp.unwrap_or(post.timestamp)),
+        }
+    }
+
+    /// Whether `post` is this one, or was liked before it. Likes come newest
+    /// first, so this still stops on time when the marked post was unliked.
+    pub fn reached(&self, post: &Post) -> bool {
+        post.id == self.id
+            || self
+                .timestamp
+                .is_some_and(|t| post.liked_timestamp.unwrap_or(post.timestamp) <= t)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SavedMarker {
+    /// Older versions only saved the ID.
+    Id(u64),
+    Marker {
+        id: u64,
+        timestamp: Option<u64>,
+    },
+}
+
+impl From<SavedMarker> for Marker {
+    fn from(saved: SavedMarker) -> Marker {
+        match saved {
+            SavedMarker::Id(id) => Marker {
+                id,
+                timestamp: None,
+            },
+            SavedMarker::Marker { id, timestamp } => Marker { id, timestamp },
+        }
+    }
+}
+
+impl SyncState {
+    /// Loads the sync state from `directory`, or an empty one if there is none yet.
+    pub fn load(directory: &str) -> io::Result<SyncState> {
+        let file = match File::open(sync_path(directory)) {
+            Ok(f) => f,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(SyncState::default()),
+            Err(e) => return Err(e),
+        };
+
+        Ok(serde_json::from_reader(BufReader::new(file))?)
+    }
+
+    pub fn save(&self, directory: &str) -> io::Result<()> {
+        let path = sync_path(directory);
+        let tmp = path.with_extension("json.tmp");
+
+        fs::create_dir_all(directory)?;
+
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        fs::rename(tmp, path)
+    }
+}
+
+fn sync_path(directory: &str) -> PathBuf {
+    PathBuf::from(directory).join(SYNC_FILE)
+}
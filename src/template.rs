@@ -0,0 +1,265 @@
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::types::Post;
+
+/// Lays out downloaded files in the same way as before templates existed.
+pub const DEFAULT_TEMPLATE: &str = "{folder}/{timestamp}_{id}_{index}.{ext}";
+
+/// A path template for downloaded media, e.g. `{blog_name}/{year}/{id}_{index}.{ext}`.
+#[derive(Debug, Clone)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone)]
+enum Part {
+    Literal(String),
+    Field(Field),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Field {
+    BlogName,
+    Id,
+    Kind,
+    Folder,
+    Date,
+    Year,
+    Month,
+    Day,
+    Timestamp,
+    LikedTimestamp,
+    Tags,
+    Index,
+    Filename,
+    Ext,
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Field> {
+        Some(match name {
+            "blog_name" => Field::BlogName,
+            "id" => Field::Id,
+            "kind" => Field::Kind,
+            "folder" => Field::Folder,
+            "date" => Field::Date,
+            "year" => Field::Year,
+            "month" => Field::Month,
+            "day" => Field::Day,
+            "timestamp" => Field::Timestamp,
+            "liked_timestamp" => Field::LikedTimestamp,
+            "tags" => Field::Tags,
+            "index" => Field::Index,
+            "filename" => Field::Filename,
+            "ext" => Field::Ext,
+            _ => return None,
+        })
+    }
+}
+
+impl FromStr for Template {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Template, String> {
+        let mut parts = Vec::new();
+        let mut rest = s;
+
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(Part::Literal(rest[..start].to_string()));
+            }
+
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| format!("unclosed placeholder in \"{}\"", s))?;
+            let name = &rest[start + 1..start + end];
+            let field = Field::from_name(name)
+                .ok_or_else(|| format!("unknown placeholder {{{}}}", name))?;
+
+            parts.push(Part::Field(field));
+            rest = &rest[start + end + 1..];
+        }
+
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_string()));
+        }
+
+        // Otherwise different files would be saved to the same place, and
+        // later ones taken for already downloaded
+        let has = |field: fn(&Field) -> bool| {
+            parts
+                .iter()
+                .any(|p| matches!(p, Part::Field(f) if field(f)))
+        };
+        let unique = has(|f| matches!(f, Field::Filename))
+            || (has(|f| matches!(f, Field::Id)) && has(|f| matches!(f, Field::Index)));
+
+        if !unique {
+            return Err(format!(
+                "\"{}\" needs {{filename}}, or both {{id}} and {{index}}, to tell files apart",
+                s
+            ));
+        }
+
+        Ok(Template { parts })
+    }
+}
+
+impl Template {
+    /// Renders the path, relative to the download directory, of the `index`th
+    /// media file of `post`. `folder` is the media kind's default folder, like `pics`.
+    pub fn render(&self, post: &Post, folder: &str, index: usize, url: &str) -> String {
+        // The last part of the URL's path, without any query
+        let path = url.split(['?', '#']).next().unwrap_or_default();
+        let basename = path.rsplit('/').find(|s| !s.is_empty()).unwrap_or_default();
+        let basename = Path::new(basename);
+
+        // Tumblr dates look like "2020-09-13 12:26:40 GMT"
+        let date = post.date.split(' ').next().unwrap_or_default();
+        let mut ymd = date.splitn(3, '-');
+        let (year, month, day) = (ymd.next(), ymd.next(), ymd.next());
+
+        let mut path = String::new();
+
+        for part in &self.parts {
+            let value = match part {
+                Part::Literal(s) => {
+                    path.push_str(s);
+                    continue;
+                }
+                Part::Field(field) => match field {
+                    Field::BlogName => post.blog_name.clone(),
+                    Field::Id => post.id.to_string(),
+                    Field::Kind => post.type_name().to_string(),
+                    Field::Folder => folder.to_string(),
+                    Field::Date => date.to_string(),
+                    Field::Year => year.unwrap_or_default().to_string(),
+                    Field::Month => month.unwrap_or_default().to_string(),
+                    Field::Day => day.unwrap_or_default().to_string(),
+                    Field::Timestamp => post.timestamp.to_string(),
+                    Field::LikedTimestamp => {
+                        post.liked_timestamp.unwrap_or(post.timestamp).to_string()
+                    }
+                    Field::Tags => post.tags.join(","),
+                    Field::Index => index.to_string(),
+                    Field::Filename => basename
+                        .file_stem()
+                        .map(|s| s.to_string_lossy().to_string())
+                        .unwrap_or_default(),
+                    Field::Ext => basename
+                        .extension()
+                        .map(|s| s.to_string_lossy().to_string())
+                        .unwrap_or_default(),
+                },
+            };
+
+            path.push_str(&sanitize(&value));
+        }
+
+        // Media without an extension would otherwise end in a dot
+        path.trim_end_matches('.').to_string()
+    }
+}
+
+/// Keeps post data from adding directories or characters that aren't valid in file names.
+fn sanitize(value: &str) -> String {
+    let value: String = value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+
+    if value == "." || value == ".." {
+        value.replace('.', "_")
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn post() -> Post {
+        serde_json::from_value(json!({
+            "id": 123,
+            "type": "photo",
+            "blog_name": "some/blog",
+            "timestamp": 1600000000,
+            "liked_timestamp": 1700000000,
+            "date": "2020-09-13 12:26:40 GMT",
+            "tags": ["a", "b"],
+        }))
+        .unwrap()
+    }
+
+    fn render(template: &str, url: &str) -> String {
+        template
+            .parse::<Template>()
+            .unwrap()
+            .render(&post(), "pics", 2, url)
+    }
+
+    #[test]
+    fn needs_a_unique_name_for_every_file() {
+        for template in [
+            "{blog_name}/{index}.{ext}",
+            "{year}/{index}.{ext}",
+            "{id}.{ext}",
+            "{timestamp}_{index}.{ext}",
+        ] {
+            assert!(template.parse::<Template>().is_err(), "{}", template);
+        }
+
+        for template in [DEFAULT_TEMPLATE, "{filename}.{ext}", "{index}/{id}.{ext}"] {
+            assert!(template.parse::<Template>().is_ok(), "{}", template);
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_and_unclosed_placeholders() {
+        assert!("{nope}/{filename}".parse::<Template>().is_err());
+        assert!("{filename".parse::<Template>().is_err());
+    }
+
+    #[test]
+    fn renders_post_fields() {
+        assert_eq!(
+            render(DEFAULT_TEMPLATE, "https://x/abc/tumblr_xyz_1280.jpg"),
+            "pics/1600000000_123_2.jpg"
+        );
+        assert_eq!(
+            render(
+                "{blog_name}/{year}-{month}-{day}/{liked_timestamp}_{tags}_{kind}_{filename}.{ext}",
+                "https://x/abc/tumblr_xyz_1280.jpg?query"
+            ),
+            "some_blog/2020-09-13/1700000000_a,b_photo_tumblr_xyz_1280.jpg"
+        );
+    }
+
+    #[test]
+    fn names_blocks_posts_by_their_original_type() {
+        let post: Post = serde_json::from_value(json!({
+            "id": 123,
+            "type": "blocks",
+            "original_type": "video",
+        }))
+        .unwrap();
+        let template: Template = "{kind}/{id}_{index}".parse().unwrap();
+
+        assert_eq!(
+            template.render(&post, "videos", 0, "https://x/v.mp4"),
+            "video/123_0"
+        );
+    }
+
+    #[test]
+    fn leaves_off_a_missing_extension() {
+        assert_eq!(render("{id}_{index}.{ext}", "https://x/file"), "123_2");
+    }
+}
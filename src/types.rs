@@ -1,55 +1,567 @@
+use serde::de::{DeserializeOwned, Deserializer};
+use serde::Deserialize as _;
 use serde_derive::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::convert::TryFrom;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+use crate::photo_size::PhotoSize;
+
+/// Falls back to the default when a field is missing, null or of an unexpected
+/// type, rather than rejecting the whole post.
+fn lenient<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned + Default,
+{
+    let value = Value::deserialize(deserializer)?;
+    Ok(T::deserialize(value).unwrap_or_default())
+}
+
+/// Reads a list an item at a time, leaving out the items that can't be read
+/// rather than the whole list.
+fn lenient_items<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    Ok(lenient_optional_items(deserializer)?.unwrap_or_default())
+}
+
+/// Like [`lenient_items`], for lists that are sometimes left out.
+fn lenient_optional_items<'de, D, T>(deserializer: D) -> Result<Option<Vec<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    Ok(match Value::deserialize(deserializer)? {
+        Value::Array(items) => Some(
+            items
+                .into_iter()
+                .filter_map(|item| T::deserialize(item).ok())
+                .collect(),
+        ),
+        _ => None,
+    })
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
 pub struct BlogItem {
+    #[serde(default, deserialize_with = "lenient")]
     pub name: String,
+    #[serde(default, deserialize_with = "lenient")]
     pub active: bool,
+    /// Every field not modelled above, like `theme`.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
 pub struct PostItem {
+    #[serde(default, deserialize_with = "lenient")]
     pub id: String,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct TrailItem {
+    #[serde(default, deserialize_with = "lenient")]
     pub blog: BlogItem,
+    #[serde(default, deserialize_with = "lenient")]
     pub post: PostItem,
+    /// Empty for Neue Post Format posts, which use `content` instead.
+    #[serde(default, deserialize_with = "lenient")]
     pub content_raw: String,
+    #[serde(default, deserialize_with = "lenient_optional_items")]
+    pub content: Option<Vec<ContentBlock>>,
+    #[serde(default, deserialize_with = "lenient_optional_items")]
+    pub layout: Option<Vec<LayoutBlock>>,
+    /// Every field not modelled above, like `is_root_item`.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// A piece of media in a Neue Post Format block, one of possibly several sizes.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MediaObject {
+    pub url: String,
+    #[serde(rename = "type")]
+    pub mime_type: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
 }
 
+/// Styling applied to a range of characters in a text block.
 #[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Formatting {
+    pub start: usize,
+    pub end: usize,
+    /// One of "bold", "italic", "strikethrough", "small", "link", "mention" or "color".
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub url: Option<String>,
+    pub blog: Option<BlogItem>,
+    pub hex: Option<String>,
+}
+
+/// A Neue Post Format content block.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ContentBlock {
+    Text {
+        text: String,
+        subtype: Option<String>,
+        formatting: Option<Vec<Formatting>>,
+    },
+    Image {
+        media: Vec<MediaObject>,
+        alt_text: Option<String>,
+    },
+    Video {
+        url: Option<String>,
+        media: Option<MediaObject>,
+        provider: Option<String>,
+        poster: Option<Vec<MediaObject>>,
+    },
+    Audio {
+        url: Option<String>,
+        media: Option<MediaObject>,
+        provider: Option<String>,
+        title: Option<String>,
+        artist: Option<String>,
+        album: Option<String>,
+        poster: Option<Vec<MediaObject>>,
+    },
+    Link {
+        url: String,
+        title: Option<String>,
+        description: Option<String>,
+        poster: Option<Vec<MediaObject>>,
+    },
+    /// Block types we don't handle yet, like polls.
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LayoutRow {
+    pub blocks: Vec<usize>,
+}
+
+/// How content blocks are arranged. Only "rows" layouts affect rendering.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LayoutBlock {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub display: Option<Vec<LayoutRow>>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
 pub struct Photo {
+    #[serde(default, deserialize_with = "lenient")]
     pub url: String,
+    #[serde(default, deserialize_with = "lenient")]
     pub width: i32,
+    #[serde(default, deserialize_with = "lenient")]
     pub height: i32,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Photos {
+    #[serde(default, deserialize_with = "lenient")]
     pub caption: String,
-    pub original_size: Photo,
+    /// Missing from some photos, which then only have `alt_sizes`.
+    #[serde(default, deserialize_with = "lenient")]
+    pub original_size: Option<Photo>,
+    /// Other sizes of the same photo, sometimes including larger ones.
+    #[serde(default, deserialize_with = "lenient_items")]
+    pub alt_sizes: Vec<Photo>,
+    /// Every field not modelled above, like `exif`.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl Photos {
+    /// The URL of the requested size of this photo, if it has any.
+    pub fn url(&self, size: &PhotoSize) -> Option<&str> {
+        let sizes = self
+            .sizes()
+            .map(|p| (p.width.max(0) as u32, p.url.as_str()));
+
+        size.pick(sizes)
+    }
+
+    /// Every size of this photo.
+    pub fn sizes(&self) -> impl Iterator<Item = &Photo> {
+        self.original_size
+            .iter()
+            .chain(&self.alt_sizes)
+            .filter(|p| !p.url.is_empty())
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(try_from = "Map<String, Value>", into = "Map<String, Value>")]
 pub struct Post {
     pub blog_name: String,
     pub id: u64,
     pub post_url: String,
-    #[serde(rename = "type")]
-    pub kind: String,
     pub timestamp: u64,
+    /// When the post was liked. Only sent along with likes.
+    pub liked_timestamp: Option<u64>,
     pub date: String,
     pub format: String,
     pub note_count: u64,
     pub reblog_key: String,
     pub tags: Vec<String>,
-    pub body: Option<String>,
     pub trail: Option<Vec<TrailItem>>,
-    pub photos: Option<Vec<Photos>>,
+    /// Neue Post Format content, sent instead of the type's fields when requested.
+    pub content: Option<Vec<ContentBlock>>,
+    pub layout: Option<Vec<LayoutBlock>>,
+    pub kind: PostKind,
+    /// Every field not modelled above, and fields that could only be read in
+    /// part as they were sent, so dumps keep the full original post.
+    pub extra: Map<String, Value>,
+}
+
+impl Post {
+    /// The post's type, like "photo". Neue Post Format posts keep theirs in
+    /// `original_type`.
+    pub fn type_name(&self) -> &str {
+        match self.kind {
+            PostKind::Blocks => self
+                .extra
+                .get("original_type")
+                .and_then(Value::as_str)
+                .unwrap_or("blocks"),
+            _ => self.kind.name(),
+        }
+    }
+}
+
+/// A post as it is sent over the wire. Fields that `PostKind` doesn't take
+/// end up in `Post::extra`.
+#[derive(Serialize, Deserialize)]
+struct RawPost {
+    id: u64,
+    #[serde(default, deserialize_with = "lenient")]
+    blog_name: String,
+    #[serde(default, deserialize_with = "lenient")]
+    post_url: String,
+    #[serde(default, deserialize_with = "lenient")]
+    timestamp: u64,
+    #[serde(default, deserialize_with = "lenient")]
+    liked_timestamp: Option<u64>,
+    #[serde(default, deserialize_with = "lenient")]
+    date: String,
+    #[serde(default, deserialize_with = "lenient")]
+    format: String,
+    #[serde(default, deserialize_with = "lenient")]
+    note_count: u64,
+    #[serde(default, deserialize_with = "lenient")]
+    reblog_key: String,
+    #[serde(default, deserialize_with = "lenient_items")]
+    tags: Vec<String>,
+    #[serde(default, deserialize_with = "lenient_optional_items")]
+    trail: Option<Vec<TrailItem>>,
+    #[serde(default, deserialize_with = "lenient_optional_items")]
+    content: Option<Vec<ContentBlock>>,
+    #[serde(default, deserialize_with = "lenient_optional_items")]
+    layout: Option<Vec<LayoutBlock>>,
+    #[serde(flatten)]
+    rest: Map<String, Value>,
+}
+
+impl TryFrom<Map<String, Value>> for Post {
+    type Error = serde_json::Error;
+
+    fn try_from(sent: Map<String, Value>) -> Result<Post, serde_json::Error> {
+        let raw: RawPost = serde_json::from_value(Value::Object(sent.clone()))?;
+
+        // A type that isn't even a string is as unknown as any other
+        let kind =
+            PostKind::deserialize(Value::Object(raw.rest.clone())).unwrap_or(PostKind::Unknown);
+        let mut extra = raw.rest;
+
+        // Leave out what the kind took, except the type of unknown posts
+        if let Value::Object(taken) = serde_json::to_value(&kind)? {
+            if !matches!(kind, PostKind::Unknown) {
+                for key in taken.keys() {
+                    extra.remove(key);
+                }
+            }
+        }
+
+        let mut post = Post {
+            blog_name: raw.blog_name,
+            id: raw.id,
+            post_url: raw.post_url,
+            timestamp: raw.timestamp,
+            liked_timestamp: raw.liked_timestamp,
+            date: raw.date,
+            format: raw.format,
+            note_count: raw.note_count,
+            reblog_key: raw.reblog_key,
+            tags: raw.tags,
+            trail: raw.trail,
+            content: raw.content,
+            layout: raw.layout,
+            kind,
+            extra,
+        };
+
+        // Keep whatever doesn't read back the same as it was sent, so dumps
+        // don't lose anything the fields above left out
+        let read = Map::from(post.clone());
+
+        for (key, value) in sent {
+            if read.get(&key) != Some(&value) {
+                post.extra.insert(key, value);
+            }
+        }
+
+        Ok(post)
+    }
+}
+
+impl From<Post> for Map<String, Value> {
+    fn from(post: Post) -> Map<String, Value> {
+        let mut rest = Map::new();
+
+        if !matches!(post.kind, PostKind::Unknown) {
+            if let Ok(Value::Object(kind)) = serde_json::to_value(&post.kind) {
+                rest = kind;
+            }
+        }
+
+        let raw = RawPost {
+            id: post.id,
+            blog_name: post.blog_name,
+            post_url: post.post_url,
+            timestamp: post.timestamp,
+            liked_timestamp: post.liked_timestamp,
+            date: post.date,
+            format: post.format,
+            note_count: post.note_count,
+            reblog_key: post.reblog_key,
+            tags: post.tags,
+            trail: post.trail,
+            content: post.content,
+            layout: post.layout,
+            rest,
+        };
+
+        let mut map = match serde_json::to_value(raw) {
+            Ok(Value::Object(map)) => map,
+            _ => Map::new(),
+        };
+
+        // Fields that weren't sent are left out, rather than made up
+        map.retain(|key, value| key == "id" || !is_empty(value));
+        map.extend(post.extra);
+        map
+    }
+}
+
+/// Whether `value` is what a field that wasn't sent reads as.
+fn is_empty(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::Bool(b) => !b,
+        Value::Number(n) => n.as_f64() == Some(0.0),
+        Value::String(s) => s.is_empty(),
+        Value::Array(a) => a.is_empty(),
+        Value::Object(o) => o.is_empty(),
+    }
+}
+
+/// Parses each post on its own, skipping the ones that are malformed. Returns
+/// the posts, and a message for each one skipped.
+pub fn parse_posts(values: Vec<Value>) -> (Vec<Post>, Vec<String>) {
+    let mut posts = Vec::new();
+    let mut skipped = Vec::new();
+
+    for value in values {
+        let id = value.get("id").cloned().unwrap_or(Value::Null);
+
+        match serde_json::from_value(value) {
+            Ok(post) => posts.push(post),
+            Err(e) => skipped.push(format!(
+                "Skipping post {} that could not be read: {}",
+                id, e
+            )),
+        }
+    }
+
+    (posts, skipped)
+}
+
+/// Declares `PostKind` from one list of the types with fields of their own,
+/// so the names serde reads, `name` and `PostKind::TYPES` can't drift apart.
+macro_rules! post_kinds {
+    ($($variant:ident($post:ident) => $name:literal,)*) => {
+        /// The type of a post, along with the fields only that type has.
+        #[derive(Debug, Serialize, Deserialize, Clone)]
+        #[serde(tag = "type")]
+        pub enum PostKind {
+            $(
+                #[serde(rename = $name)]
+                $variant($post),
+            )*
+            /// Neue Post Format posts, whose content is in `Post::content`.
+            #[serde(rename = "blocks")]
+            Blocks,
+            /// Types we don't know about yet. Their fields are kept in `Post::extra`.
+            #[serde(other)]
+            Unknown,
+        }
+
+        impl PostKind {
+            /// The names of the types with fields of their own.
+            pub const TYPES: &'static [&'static str] = &[$($name),*];
+
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(PostKind::$variant(_) => $name,)*
+                    PostKind::Blocks => "blocks",
+                    PostKind::Unknown => "unknown",
+                }
+            }
+        }
+    };
+}
+
+post_kinds! {
+    Text(TextPost) => "text",
+    Photo(PhotoPost) => "photo",
+    Quote(QuotePost) => "quote",
+    Link(LinkPost) => "link",
+    Chat(ChatPost) => "chat",
+    Audio(AudioPost) => "audio",
+    Video(VideoPost) => "video",
+    Answer(AnswerPost) => "answer",
+}
+
+impl PostKind {
+    /// The fields of this post that hold HTML.
+    pub fn html(&self) -> Vec<&String> {
+        let fields = match self {
+            PostKind::Text(p) => vec![&p.body],
+            PostKind::Photo(p) => vec![&p.caption],
+            PostKind::Quote(p) => vec![&p.text, &p.source],
+            PostKind::Link(p) => vec![&p.description],
+            PostKind::Chat(_) => vec![],
+            PostKind::Audio(p) => vec![&p.caption],
+            PostKind::Video(p) => vec![&p.caption],
+            PostKind::Answer(p) => vec![&p.question, &p.answer],
+            PostKind::Blocks | PostKind::Unknown => vec![],
+        };
+
+        fields.into_iter().flatten().collect()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TextPost {
+    #[serde(default, deserialize_with = "lenient")]
+    pub title: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub body: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PhotoPost {
+    #[serde(default, deserialize_with = "lenient")]
+    pub caption: Option<String>,
+    #[serde(default, deserialize_with = "lenient_items")]
+    pub photos: Vec<Photos>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct QuotePost {
+    #[serde(default, deserialize_with = "lenient")]
+    pub text: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub source: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LinkPost {
+    #[serde(default, deserialize_with = "lenient")]
+    pub title: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub url: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChatLine {
+    pub label: String,
+    pub name: String,
+    pub phrase: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChatPost {
+    #[serde(default, deserialize_with = "lenient")]
+    pub title: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub body: Option<String>,
+    #[serde(default, deserialize_with = "lenient_items")]
+    pub dialogue: Vec<ChatLine>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AudioPost {
+    #[serde(default, deserialize_with = "lenient")]
+    pub caption: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub audio_url: Option<String>,
+    /// Where the audio is hosted, like "tumblr" or "spotify".
+    #[serde(default, deserialize_with = "lenient")]
+    pub audio_type: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub album_art: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub track_name: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub artist: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub album: Option<String>,
+}
+
+impl AudioPost {
+    /// The audio file, if it is hosted by Tumblr and so can be downloaded.
+    /// Audio from elsewhere, like Spotify, only links to a player page.
+    pub fn tumblr_audio_url(&self) -> Option<&String> {
+        match self.audio_type.as_deref() {
+            None | Some("tumblr") => self.audio_url.as_ref(),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct VideoPost {
+    #[serde(default, deserialize_with = "lenient")]
+    pub caption: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
     pub video_url: Option<String>,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AnswerPost {
+    #[serde(default, deserialize_with = "lenient")]
+    pub asking_name: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub question: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub answer: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct QueryParams {
     pub limit: String,
@@ -68,8 +580,13 @@ pub struct Links {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Response {
+    #[serde(default, deserialize_with = "lenient")]
     pub liked_count: i32,
-    pub liked_posts: Vec<Post>,
+    /// Read a post at a time with [`parse_posts`], so one bad post doesn't
+    /// lose the whole page.
+    #[serde(default, deserialize_with = "lenient")]
+    pub liked_posts: Vec<Value>,
+    #[serde(default, deserialize_with = "lenient")]
     pub _links: Option<Links>,
 }
 
@@ -77,3 +594,30 @@ pub struct Response {
 pub struct ReturnVal {
     pub response: Response,
 }
+
+/// The status every API response carries, e.g. `{"status": 404, "msg": "Not Found"}`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Meta {
+    pub status: u16,
+    #[serde(default)]
+    pub msg: String,
+}
+
+/// One of the reasons a request failed.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiError {
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub code: Option<u32>,
+    #[serde(default)]
+    pub detail: Option<String>,
+}
+
+/// What the API sends instead of a response when a request fails.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ErrorEnvelope {
+    pub meta: Meta,
+    #[serde(default, deserialize_with = "lenient")]
+    pub errors: Vec<ApiError>,
+}
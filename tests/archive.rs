@@ -0,0 +1,251 @@
+//! End to end runs of the binary against a local stand-in for Tumblr's API and
+//! media servers.
+
+mod common;
+
+use common::{post, tumblr, BLOG, POSTS};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+use tempfile::TempDir;
+use wiremock::http::HeaderName;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn run(server: &MockServer, dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_tumblr-likes"))
+        .current_dir(dir)
+        .env_clear()
+        .args(["--api-host", &server.uri(), "--media-host", &server.uri()])
+        .args(["--retries", "0"])
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn assert_success(output: &Output) {
+    assert!(
+        output.status.success(),
+        "exited with {}: {}",
+        output.status,
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+/// Checks that every post's media was downloaded under `dir`, with the right contents.
+fn assert_downloaded(dir: &Path) {
+    for id in 1..=POSTS {
+        let (file, contents) = if id.is_multiple_of(2) {
+            (
+                format!("pics/{}_{}_0.jpg", 1_600_000_000 + id, id),
+                format!("/abc/p{}_1280.jpg", id),
+            )
+        } else {
+            (
+                format!("videos/{}_{}_0.mp4", 1_600_000_000 + id, id),
+                format!("/v{}.mp4", id),
+            )
+        };
+
+        assert_eq!(fs::read_to_string(dir.join(file)).unwrap(), contents);
+    }
+}
+
+#[tokio::test]
+async fn downloads_every_page() {
+    let server = tumblr().await;
+    let dir = TempDir::new().unwrap();
+
+    let output = run(&server, dir.path(), &["-a", "key", "-b", BLOG, "-d", "out"]);
+    assert_success(&output);
+
+    let out = dir.path().join("out");
+    assert_downloaded(&out);
+
+    let manifest: Value =
+        serde_json::from_str(&fs::read_to_string(out.join("manifest.json")).unwrap()).unwrap();
+    assert_eq!(manifest["posts"].as_object().unwrap().len(), POSTS as usize);
+
+    // The info request, then two pages of twenty
+    let pages: Vec<String> = server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter(|r| r.url.path().ends_with("/likes"))
+        .map(|r| r.url.query().unwrap_or_default().to_string())
+        .collect();
+
+    assert_eq!(pages.len(), 3);
+    assert!(pages[1].ends_with("limit=20"));
+    assert!(pages[2].ends_with(&format!("before={}", 1_700_000_000 + POSTS - 19)));
+}
+
+#[tokio::test]
+async fn dumps_and_restores() {
+    let server = tumblr().await;
+    let dir = TempDir::new().unwrap();
+
+    let output = run(
+        &server,
+        dir.path(),
+        &["-a", "key", "-b", BLOG, "--dump", "likes.json"],
+    );
+    assert_success(&output);
+
+    let dump: Vec<Value> =
+        serde_json::from_str(&fs::read_to_string(dir.path().join("likes.json")).unwrap()).unwrap();
+    // Every post is dumped just as it was served, newest first
+    let served: Vec<Value> = (1..=POSTS).rev().map(post).collect();
+    assert_eq!(dump, served);
+
+    // Restoring makes no API requests
+    let server = tumblr().await;
+
+    let output = run(
+        &server,
+        dir.path(),
+        &["--restore", "likes.json", "-d", "out"],
+    );
+    assert_success(&output);
+    assert_downloaded(&dir.path().join("out"));
+
+    let requests = server.received_requests().await.unwrap();
+    assert!(requests.iter().all(|r| !r.url.path().contains("/v2/")));
+}
+
+#[tokio::test]
+async fn exports_html() {
+    let server = tumblr().await;
+    let dir = TempDir::new().unwrap();
+
+    let output = run(
+        &server,
+        dir.path(),
+        &["-a", "key", "-b", BLOG, "-e", "likes.html"],
+    );
+    assert_success(&output);
+
+    let html = fs::read_to_string(dir.path().join("likes.html")).unwrap();
+    assert_eq!(html.matches("<div class='card'>").count(), POSTS as usize);
+    assert!(html.contains("<img src='export/p2_1280.jpg' />"));
+    assert!(html.contains("<source src='export/v1.mp4'>"));
+
+    assert_eq!(
+        fs::read_to_string(dir.path().join("export/p2_1280.jpg")).unwrap(),
+        "/abc/p2_1280.jpg"
+    );
+}
+
+#[tokio::test]
+async fn only_remembers_unfiltered_runs() {
+    let server = tumblr().await;
+    let dir = TempDir::new().unwrap();
+    let sync = || -> Value {
+        serde_json::from_str(&fs::read_to_string(dir.path().join("out/sync.json")).unwrap())
+            .unwrap()
+    };
+
+    // Videos were left out, so a later run mustn't skip them
+    let output = run(
+        &server,
+        dir.path(),
+        &["-a", "key", "-b", BLOG, "-d", "out", "--type", "photo"],
+    );
+    assert_success(&output);
+    assert_eq!(sync()["newest"], json!({}));
+
+    let output = run(&server, dir.path(), &["-a", "key", "-b", BLOG, "-d", "out"]);
+    assert_success(&output);
+    assert_downloaded(&dir.path().join("out"));
+    assert_eq!(
+        sync()["newest"][BLOG],
+        json!({ "id": POSTS, "timestamp": 1_700_000_000 + POSTS })
+    );
+}
+
+#[tokio::test]
+async fn tries_failed_downloads_again() {
+    let server = tumblr().await;
+    let dir = TempDir::new().unwrap();
+
+    Mock::given(path("/abc/p10_1280.jpg"))
+        .respond_with(ResponseTemplate::new(404))
+        .with_priority(1)
+        .mount(&server)
+        .await;
+
+    let output = run(&server, dir.path(), &["-a", "key", "-b", BLOG, "-d", "out"]);
+    assert_success(&output);
+    assert!(!dir.path().join("out/pics/1600000010_10_0.jpg").exists());
+
+    // The next incremental run stops below the failed post, so it's tried again
+    let sync: Value =
+        serde_json::from_str(&fs::read_to_string(dir.path().join("out/sync.json")).unwrap())
+            .unwrap();
+    assert_eq!(sync["newest"][BLOG]["id"], 9);
+}
+
+#[tokio::test]
+async fn signs_blog_likes_when_signed_in() {
+    let server = tumblr().await;
+    let dir = TempDir::new().unwrap();
+
+    let output = run(
+        &server,
+        dir.path(),
+        &[
+            "-a",
+            "key",
+            "-b",
+            BLOG,
+            "-d",
+            "out",
+            "--consumer-secret",
+            "secret",
+            "--token",
+            "token",
+            "--token-secret",
+            "token secret",
+        ],
+    );
+    assert_success(&output);
+    assert_downloaded(&dir.path().join("out"));
+
+    // The blog's likes, not the user's own
+    let requests = server.received_requests().await.unwrap();
+    let likes: Vec<_> = requests
+        .iter()
+        .filter(|r| r.url.path().ends_with("/likes"))
+        .collect();
+
+    assert_eq!(likes.len(), 3);
+    assert!(likes.iter().all(|r| {
+        r.url.path() == format!("/v2/blog/{}/likes", BLOG)
+            && r.headers
+                .get(&HeaderName::from("authorization"))
+                .is_some_and(|v| v.as_str().starts_with("OAuth "))
+    }));
+}
+
+#[tokio::test]
+async fn reports_a_missing_blog() {
+    let server = MockServer::start().await;
+    let dir = TempDir::new().unwrap();
+
+    Mock::given(method("GET"))
+        .and(path("/v2/blog/nobody/likes"))
+        .respond_with(ResponseTemplate::new(404).set_body_json(json!({
+            "meta": { "status": 404, "msg": "Not Found" },
+            "response": [],
+            "errors": [{ "title": "Not Found", "code": 0 }],
+        })))
+        .mount(&server)
+        .await;
+
+    let output = run(&server, dir.path(), &["-a", "key", "-b", "nobody"]);
+
+    assert_eq!(output.status.code(), Some(3));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("The blog nobody doesn't exist"));
+}
@@ -0,0 +1,122 @@
+//! A local stand-in for Tumblr's API and media servers, shared by the tests.
+
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use tumblr_likes::{Client, ClientOptions};
+use wiremock::matchers::{method, path, path_regex};
+use wiremock::{Mock, MockServer, Request, Respond, ResponseTemplate};
+
+pub const BLOG: &str = "liker";
+pub const POSTS: u64 = 25;
+
+/// Serves liked posts newest first, a page at a time, paging by `before` like the real API.
+pub struct LikesPages {
+    pub posts: Vec<Value>,
+}
+
+impl Respond for LikesPages {
+    fn respond(&self, req: &Request) -> ResponseTemplate {
+        let query: HashMap<String, String> = req.url.query_pairs().into_owned().collect();
+        let limit: usize = query.get("limit").map_or(20, |l| l.parse().unwrap());
+        let before: u64 = query.get("before").map_or(u64::MAX, |b| b.parse().unwrap());
+
+        let page: Vec<Value> = self
+            .posts
+            .iter()
+            .filter(|p| p["liked_timestamp"].as_u64().unwrap() < before)
+            .take(limit)
+            .cloned()
+            .collect();
+
+        let links = match page.last() {
+            Some(last) if page.len() == limit => json!({
+                "next": {
+                    "query_params": {
+                        "limit": limit.to_string(),
+                        "before": last["liked_timestamp"].to_string(),
+                    }
+                }
+            }),
+            _ => Value::Null,
+        };
+
+        ResponseTemplate::new(200).set_body_json(json!({
+            "meta": { "status": 200, "msg": "OK" },
+            "response": {
+                "liked_count": self.posts.len(),
+                "liked_posts": page,
+                "_links": links,
+            }
+        }))
+    }
+}
+
+/// Photo posts with even IDs and video posts with odd ones, with media on
+/// Tumblr's hosts so it only downloads through `--media-host`.
+pub fn post(id: u64) -> Value {
+    let mut post = json!({
+        "blog_name": format!("blog{}", id % 3),
+        "id": id,
+        "post_url": format!("https://blog{}.tumblr.com/post/{}", id % 3, id),
+        "timestamp": 1_600_000_000 + id,
+        "liked_timestamp": 1_700_000_000 + id,
+        "date": "2020-09-13 12:26:40 GMT",
+        "format": "html",
+        "note_count": id,
+        "reblog_key": "key",
+        "tags": ["tag"],
+        "trail": [],
+    });
+
+    if id.is_multiple_of(2) {
+        post["type"] = json!("photo");
+        post["photos"] = json!([{
+            "caption": "",
+            "original_size": {
+                "url": format!("https://64.media.tumblr.com/abc/p{}_1280.jpg", id),
+                "width": 1280,
+                "height": 720,
+            },
+            "alt_sizes": [],
+        }]);
+    } else {
+        post["type"] = json!("video");
+        post["video_url"] = json!(format!("https://va.media.tumblr.com/v{}.mp4", id));
+    }
+
+    post
+}
+
+/// Starts the stand-in, serving `POSTS` likes and any media file.
+pub async fn tumblr() -> MockServer {
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path(format!("/v2/blog/{}/likes", BLOG)))
+        .respond_with(LikesPages {
+            posts: (1..=POSTS).rev().map(post).collect(),
+        })
+        .mount(&server)
+        .await;
+
+    // Each file holds its own path, so it can be told apart
+    Mock::given(method("GET"))
+        .and(path_regex("^/(abc/)?[pv][0-9]+"))
+        .respond_with(|req: &Request| ResponseTemplate::new(200).set_body_string(req.url.path()))
+        .mount(&server)
+        .await;
+
+    server
+}
+
+/// A client for `server`, for both the API and media, that doesn't retry.
+/// The end to end tests run the binary instead.
+#[allow(dead_code)]
+pub fn client(server: &MockServer) -> Client {
+    let mut options = ClientOptions::new("key");
+    options.api_host = server.uri();
+    options.media_host = Some(server.uri());
+    options.retry.max_attempts = 1;
+
+    Client::new(options).unwrap()
+}
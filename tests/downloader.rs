@@ -0,0 +1,143 @@
+//! Downloading posts with `Downloader`, without the binary.
+
+mod common;
+
+use common::{client, post, tumblr};
+use indicatif::ProgressBar;
+use serde_json::json;
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+use tumblr_likes::download::track_details_url;
+use tumblr_likes::{DownloadOptions, Downloader, Error, Manifest, Post};
+use wiremock::matchers::path;
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn downloader(server: &MockServer, dir: &TempDir) -> Downloader {
+    let options = DownloadOptions {
+        directory: dir.path().to_string_lossy().to_string(),
+        ..DownloadOptions::default()
+    };
+    let manifest = Manifest::load(&options.directory).unwrap();
+
+    Downloader::new(&client(server), options, manifest)
+}
+
+fn posts(ids: &[u64]) -> Vec<Post> {
+    ids.iter()
+        .map(|&id| serde_json::from_value(post(id)).unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn hands_back_failed_downloads() {
+    let server = tumblr().await;
+    let dir = TempDir::new().unwrap();
+
+    Mock::given(path("/abc/p2_1280.jpg"))
+        .respond_with(ResponseTemplate::new(404))
+        .with_priority(1)
+        .mount(&server)
+        .await;
+
+    let downloader = downloader(&server, &dir);
+    let bar = ProgressBar::hidden();
+    let pending = downloader.download_posts(posts(&[2, 3]), &bar);
+    let done = downloader.finish(pending, &bar).await.unwrap();
+
+    assert!(!done[0].is_complete());
+    assert_eq!(done[0].failed.len(), 1);
+    assert_eq!(
+        done[0].failed[0].0,
+        "https://64.media.tumblr.com/abc/p2_1280.jpg"
+    );
+    assert!(matches!(done[0].failed[0].1, Error::Http { .. }));
+    assert!(done[1].is_complete());
+}
+
+#[tokio::test]
+async fn reports_files_cut_short() {
+    let server = tumblr().await;
+    let dir = TempDir::new().unwrap();
+
+    // Says the file is twice as long as what it sends
+    Mock::given(path("/abc/p2_1280.jpg"))
+        .respond_with(
+            ResponseTemplate::new(206)
+                .insert_header("Content-Range", "bytes 0-4/10")
+                .set_body_string("01234"),
+        )
+        .with_priority(1)
+        .mount(&server)
+        .await;
+
+    let downloader = downloader(&server, &dir);
+    let bar = ProgressBar::hidden();
+    let pending = downloader.download_posts(posts(&[2]), &bar);
+    let done = downloader.finish(pending, &bar).await.unwrap();
+
+    let error = &done[0].failed[0].1;
+    assert!(matches!(error, Error::Transfer(_)), "{:?}", error);
+    assert_eq!(error.exit_code(), 4);
+}
+
+#[tokio::test]
+async fn clones_share_the_manifest() {
+    let server = tumblr().await;
+    let dir = TempDir::new().unwrap();
+
+    let downloader = downloader(&server, &dir);
+    let clone = downloader.clone();
+    let bar = ProgressBar::hidden();
+
+    // Finishing one clone while the other is still alive keeps both posts
+    let mut pending = clone.download_posts(posts(&[2]), &bar);
+    pending.extend(downloader.download_posts(posts(&[3]), &bar));
+    downloader.finish(pending, &bar).await.unwrap();
+
+    let manifest = Manifest::load(&dir.path().to_string_lossy()).unwrap();
+    assert!(manifest.posts.contains_key(&2));
+    assert!(manifest.posts.contains_key(&3));
+}
+
+#[tokio::test]
+async fn writes_missing_track_details() {
+    let server = tumblr().await;
+    let dir = TempDir::new().unwrap();
+    let audio_url = "https://a.tumblr.com/tumblr_a1.mp3";
+
+    Mock::given(path("/tumblr_a1.mp3"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("audio"))
+        .mount(&server)
+        .await;
+
+    let post: Post = serde_json::from_value(json!({
+        "id": 7,
+        "type": "audio",
+        "timestamp": 1600000007,
+        "audio_url": audio_url,
+        "audio_type": "tumblr",
+        "track_name": "Song",
+    }))
+    .unwrap();
+    let details = dir.path().join("audio/1600000007_7_0.json");
+    let bar = ProgressBar::hidden();
+
+    for _ in 0..2 {
+        let downloader = downloader(&server, &dir);
+        let pending = downloader.download_posts(vec![post.clone()], &bar);
+        assert!(downloader.finish(pending, &bar).await.unwrap()[0].is_complete());
+
+        // Recorded, so --verify checks them too
+        let manifest = Manifest::load(&dir.path().to_string_lossy()).unwrap();
+        let recorded = manifest.archived(7, &track_details_url(audio_url)).unwrap();
+        assert_eq!(recorded.path, Path::new("audio/1600000007_7_0.json"));
+        assert!(fs::read_to_string(&details).unwrap().contains("Song"));
+
+        // Written again even though the audio is already there
+        fs::remove_file(&details).unwrap();
+    }
+
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 1);
+}
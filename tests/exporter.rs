@@ -0,0 +1,87 @@
+//! Exporting through the `Exporter` trait, with a format of our own.
+
+mod common;
+
+use common::{client, tumblr, BLOG, POSTS};
+use futures::TryStreamExt;
+use indicatif::ProgressBar;
+use std::fs;
+use std::sync::Mutex;
+use tempfile::TempDir;
+use tumblr_likes::types::PostKind;
+use tumblr_likes::{
+    export, DownloadOptions, Downloader, ExportOptions, Exporter, Likes, LikesSource, Manifest,
+    Post, ResolvedPost, Result,
+};
+
+/// Lists each photo post's files, one per line.
+#[derive(Default)]
+struct PhotoList {
+    lines: Mutex<Vec<String>>,
+}
+
+impl Exporter for PhotoList {
+    fn includes(&self, post: &Post) -> bool {
+        matches!(post.kind, PostKind::Photo(_))
+    }
+
+    fn export(&self, posts: &[ResolvedPost]) -> Result<()> {
+        let mut lines = self.lines.lock().unwrap();
+
+        for resolved in posts {
+            for path in resolved.media.values() {
+                lines.push(format!("{} {}", resolved.post.id, path.display()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn hands_resolved_posts_to_an_exporter() {
+    let server = tumblr().await;
+    let dir = TempDir::new().unwrap();
+
+    let client = client(&server);
+
+    let download = DownloadOptions {
+        directory: dir.path().join("downloads").to_string_lossy().to_string(),
+        ..DownloadOptions::default()
+    };
+    let manifest = Manifest::load(&download.directory).unwrap();
+    let downloader = Downloader::new(&client, download, manifest);
+
+    let posts: Vec<Post> = LikesSource::new(&client, Likes::Blog(BLOG.to_string()))
+        .posts()
+        .try_collect()
+        .await
+        .unwrap();
+
+    let media = dir.path().join("media");
+    let exporter = PhotoList::default();
+
+    export(
+        &downloader,
+        &ExportOptions {
+            media_directory: media.to_string_lossy().to_string(),
+        },
+        &exporter,
+        posts,
+        &ProgressBar::hidden(),
+    )
+    .await
+    .unwrap();
+
+    let lines = exporter.lines.into_inner().unwrap();
+    assert_eq!(lines.len(), POSTS as usize / 2);
+    assert!(lines.contains(&format!("2 {}", media.join("p2_1280.jpg").display())));
+    assert_eq!(
+        fs::read_to_string(media.join("p2_1280.jpg")).unwrap(),
+        "/abc/p2_1280.jpg"
+    );
+
+    // Media of posts the exporter leaves out is never fetched
+    let requests = server.received_requests().await.unwrap();
+    assert!(requests.iter().all(|r| !r.url.path().ends_with(".mp4")));
+}
@@ -0,0 +1,161 @@
+//! Paging through likes with `LikesSource`, without the binary.
+
+mod common;
+
+use common::{client, post, tumblr, LikesPages, BLOG, POSTS};
+use futures::TryStreamExt;
+use serde_json::json;
+use tumblr_likes::sync::Marker;
+use tumblr_likes::{Client, ClientOptions, Error, Likes, LikesSource, Post};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, Request, Respond, ResponseTemplate};
+
+fn source(server: &MockServer) -> LikesSource {
+    LikesSource::new(&client(server), Likes::Blog(BLOG.to_string()))
+}
+
+fn ids(posts: &[Post]) -> Vec<u64> {
+    posts.iter().map(|p| p.id).collect()
+}
+
+/// Serves likes like `LikesPages`, saying the daily quota is used up after each page.
+struct QuotaUsed(LikesPages);
+
+impl Respond for QuotaUsed {
+    fn respond(&self, req: &Request) -> ResponseTemplate {
+        self.0
+            .respond(req)
+            .insert_header("X-Ratelimit-Perday-Limit", "5000")
+            .insert_header("X-Ratelimit-Perday-Remaining", "0")
+            .insert_header("X-Ratelimit-Perday-Reset", "600")
+    }
+}
+
+#[tokio::test]
+async fn streams_every_page() {
+    let server = tumblr().await;
+
+    let posts: Vec<Post> = source(&server).posts().try_collect().await.unwrap();
+    assert_eq!(ids(&posts), (1..=POSTS).rev().collect::<Vec<_>>());
+
+    let pages: Vec<Vec<Post>> = source(&server).pages().try_collect().await.unwrap();
+    assert_eq!(pages.iter().map(Vec::len).collect::<Vec<_>>(), [20, 5]);
+}
+
+#[tokio::test]
+async fn stops_at_a_seen_post() {
+    let server = tumblr().await;
+
+    let marker = Marker {
+        id: POSTS - 3,
+        timestamp: Some(1_700_000_000 + POSTS - 3),
+    };
+
+    let posts: Vec<Post> = source(&server)
+        .stopping_at(marker)
+        .posts()
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(ids(&posts), [POSTS, POSTS - 1, POSTS - 2]);
+
+    // Only the first page was needed
+    assert_eq!(server.received_requests().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn stops_at_an_older_like_once_the_seen_one_is_gone() {
+    let server = tumblr().await;
+
+    // The marked post was unliked since, so it never turns up
+    let marker = Marker {
+        id: 1000,
+        timestamp: Some(1_700_000_000 + POSTS - 3),
+    };
+
+    let posts: Vec<Post> = source(&server)
+        .stopping_at(marker)
+        .posts()
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(ids(&posts), [POSTS, POSTS - 1, POSTS - 2]);
+}
+
+#[tokio::test]
+async fn starts_before_a_cursor() {
+    let server = tumblr().await;
+
+    let posts: Vec<Post> = source(&server)
+        .starting_before((1_700_000_000 + 3).to_string())
+        .posts()
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(ids(&posts), [2, 1]);
+}
+
+#[tokio::test]
+async fn skips_posts_that_cannot_be_read() {
+    let server = MockServer::start().await;
+    let mut posts: Vec<_> = (1..=3).rev().map(post).collect();
+    posts[1]["id"] = json!("not a number");
+
+    Mock::given(method("GET"))
+        .and(path(format!("/v2/blog/{}/likes", BLOG)))
+        .respond_with(LikesPages { posts })
+        .mount(&server)
+        .await;
+
+    let page = client(&server)
+        .likes_page(&Likes::Blog(BLOG.to_string()), 20, None)
+        .await
+        .unwrap();
+
+    assert_eq!(ids(&page.posts), [3, 1]);
+    assert_eq!(page.skipped.len(), 1);
+    assert!(page.skipped[0].contains("\"not a number\""));
+}
+
+#[tokio::test]
+async fn ends_when_the_daily_quota_is_used() {
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path(format!("/v2/blog/{}/likes", BLOG)))
+        .respond_with(QuotaUsed(LikesPages {
+            posts: (1..=POSTS).rev().map(post).collect(),
+        }))
+        .mount(&server)
+        .await;
+
+    let mut posts = Vec::new();
+    let mut stream = Box::pin(source(&server).posts());
+
+    let error = loop {
+        match stream.try_next().await {
+            Ok(Some(post)) => posts.push(post),
+            Ok(None) => panic!("the stream ended without an error"),
+            Err(e) => break e,
+        }
+    };
+
+    // The first page arrives, then the cursor for the second is handed back
+    assert_eq!(posts.len(), 20);
+
+    match error {
+        Error::DailyLimit { resume, reset } => {
+            assert_eq!(resume, (1_700_000_000 + POSTS - 19).to_string());
+            assert_eq!(reset.as_secs(), 600);
+        }
+        e => panic!("expected a daily limit error, got {:?}", e),
+    }
+}
+
+#[test]
+fn rejects_an_invalid_api_host() {
+    let mut options = ClientOptions::new("key");
+    options.api_host = "api.tumblr.com".to_string();
+
+    assert!(matches!(Client::new(options), Err(Error::Config(_))));
+}
@@ -0,0 +1,31 @@
+//! Signing requests with OAuth 1.0a.
+
+use reqwest::{Method, Url};
+use tumblr_likes::oauth::signature;
+
+#[test]
+fn signs_the_rfc_5849_example() {
+    // From section 1.2 of RFC 5849
+    let oauth: Vec<(String, String)> = [
+        ("oauth_consumer_key", "dpf43f3p2l4k3l03"),
+        ("oauth_token", "nnch734d00sl2jdk"),
+        ("oauth_signature_method", "HMAC-SHA1"),
+        ("oauth_timestamp", "137131202"),
+        ("oauth_nonce", "chapoH"),
+    ]
+    .iter()
+    .map(|(k, v)| (k.to_string(), v.to_string()))
+    .collect();
+
+    assert_eq!(
+        signature(
+            &Method::GET,
+            &Url::parse("http://photos.example.net/photos?file=vacation.jpg&size=original")
+                .unwrap(),
+            &oauth,
+            "kd94hf93k423kf44",
+            "pfkkdhi9sl3r4s00",
+        ),
+        "MdpQcU8iPSUjWoN/UDMsK2sui9I="
+    );
+}
@@ -0,0 +1,111 @@
+//! Reading posts that don't quite match what's expected, and dumping them back
+//! just as they were sent.
+
+use serde_json::{json, Value};
+use tumblr_likes::photo_size::PhotoSize;
+use tumblr_likes::types::{parse_posts, Photos, PostKind};
+use tumblr_likes::Post;
+
+fn photo_post() -> Value {
+    json!({
+        "blog_name": "blog",
+        "id": 1,
+        "type": "photo",
+        "reblog_key": "key",
+        "format": "html",
+        "photos": [{
+            "caption": "",
+            "original_size": {
+                "url": "https://64.media.tumblr.com/p1_1280.jpg",
+                "width": 1280,
+                "height": 720,
+            },
+            "alt_sizes": [],
+        }],
+    })
+}
+
+/// Reads `value`, which must not be skipped, and checks it dumps unchanged.
+fn read(value: Value) -> Post {
+    let (mut posts, skipped) = parse_posts(vec![value.clone()]);
+    assert!(skipped.is_empty(), "{:?}", skipped);
+
+    let post = posts.remove(0);
+    assert_eq!(serde_json::to_value(&post).unwrap(), value);
+    post
+}
+
+fn photos(post: &Post) -> &[Photos] {
+    match &post.kind {
+        PostKind::Photo(p) => &p.photos,
+        kind => panic!("not a photo post: {:?}", kind),
+    }
+}
+
+#[test]
+fn reads_posts_as_sent() {
+    let post = read(photo_post());
+
+    assert_eq!(post.reblog_key, "key");
+    assert_eq!(
+        photos(&post)[0].url(&PhotoSize::Largest),
+        Some("https://64.media.tumblr.com/p1_1280.jpg")
+    );
+}
+
+#[test]
+fn leaves_out_missing_fields() {
+    let mut value = photo_post();
+    value.as_object_mut().unwrap().remove("reblog_key");
+    value.as_object_mut().unwrap().remove("format");
+
+    let post = read(value);
+    assert_eq!(post.reblog_key, "");
+    assert_eq!(post.format, "");
+}
+
+#[test]
+fn keeps_a_caption_that_is_not_a_string() {
+    let mut value = photo_post();
+    value["photos"][0]["caption"] = json!(5);
+
+    let post = read(value);
+    assert_eq!(photos(&post)[0].caption, "");
+    assert_eq!(post.extra["photos"][0]["caption"], 5);
+}
+
+#[test]
+fn keeps_a_mistyped_nested_field() {
+    let mut value = photo_post();
+    value["photos"][0]["original_size"]["width"] = json!("wide");
+
+    // The rest of the photo can still be downloaded
+    let post = read(value);
+    assert_eq!(photos(&post)[0].sizes().next().unwrap().width, 0);
+    assert!(photos(&post)[0].url(&PhotoSize::Largest).is_some());
+}
+
+#[test]
+fn keeps_unknown_types_whole() {
+    let value = json!({ "id": 2, "type": "poll", "question": "?" });
+
+    let post = read(value);
+    assert!(matches!(post.kind, PostKind::Unknown));
+    assert_eq!(post.extra["type"], "poll");
+}
+
+#[test]
+fn skips_posts_without_an_id() {
+    let (posts, skipped) = parse_posts(vec![json!({ "type": "text" })]);
+
+    assert!(posts.is_empty());
+    assert_eq!(skipped.len(), 1);
+}
+
+#[test]
+fn reads_every_type_by_its_name() {
+    for name in PostKind::TYPES {
+        let post = read(json!({ "id": 3, "type": name }));
+        assert_eq!(post.kind.name(), *name);
+    }
+}
@@ -0,0 +1,201 @@
+//! Resuming downloads that were cut short, only while the file is unchanged.
+
+mod common;
+
+use common::{client, post, tumblr};
+use indicatif::ProgressBar;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tumblr_likes::{Client, ClientOptions, DownloadOptions, Downloader, Manifest, Post};
+use wiremock::http::HeaderName;
+use wiremock::matchers::path;
+use wiremock::{Mock, Request, Respond, ResponseTemplate};
+
+const FILE: &str = "0123456789";
+const ETAG: &str = "\"v2\"";
+const KEY: &str = "pics/1600000002_2_0.jpg";
+
+/// Serves `FILE` as version `ETAG`, sending ranges only of that version.
+struct Versioned;
+
+impl Respond for Versioned {
+    fn respond(&self, req: &Request) -> ResponseTemplate {
+        let header = |name: &str| {
+            req.headers
+                .get(&HeaderName::from(name))
+                .map(|values| values.as_str().to_string())
+        };
+        let whole = ResponseTemplate::new(200)
+            .insert_header("ETag", ETAG)
+            .set_body_string(FILE);
+
+        let start: usize = match header("range") {
+            Some(range) => range["bytes=".len()..range.len() - 1].parse().unwrap(),
+            None => return whole,
+        };
+
+        if header("if-range").as_deref() != Some(ETAG) {
+            whole
+        } else if start >= FILE.len() {
+            ResponseTemplate::new(416)
+        } else {
+            ResponseTemplate::new(206)
+                .insert_header(
+                    "Content-Range",
+                    format!("bytes {}-{}/{}", start, FILE.len() - 1, FILE.len()).as_str(),
+                )
+                .set_body_string(&FILE[start..])
+        }
+    }
+}
+
+/// Downloads post 2's photo into `dir`, after a previous attempt left
+/// `partial` behind, downloaded from version `validator` of the file.
+async fn resume(dir: &Path, partial: &str, validator: Option<&str>) -> Vec<Request> {
+    let server = tumblr().await;
+
+    Mock::given(path("/abc/p2_1280.jpg"))
+        .respond_with(Versioned)
+        .with_priority(1)
+        .mount(&server)
+        .await;
+
+    fs::create_dir_all(dir.join("pics")).unwrap();
+    fs::write(dir.join(format!("{}.part", KEY)), partial).unwrap();
+
+    if let Some(validator) = validator {
+        fs::write(dir.join(format!("{}.part.validator", KEY)), validator).unwrap();
+    }
+
+    let client = client(&server);
+
+    let download = DownloadOptions {
+        directory: dir.to_string_lossy().to_string(),
+        ..DownloadOptions::default()
+    };
+    let manifest = Manifest::load(&download.directory).unwrap();
+    let downloader = Downloader::new(&client, download, manifest);
+
+    let post: Post = serde_json::from_value(post(2)).unwrap();
+    let bar = ProgressBar::hidden();
+    let pending = downloader.download_posts(vec![post], &bar);
+    let posts = downloader.finish(pending, &bar).await.unwrap();
+    assert!(posts[0].is_complete());
+
+    assert_eq!(fs::read_to_string(dir.join(KEY)).unwrap(), FILE);
+    assert!(!dir.join(format!("{}.part", KEY)).exists());
+    assert!(!dir.join(format!("{}.part.validator", KEY)).exists());
+
+    server.received_requests().await.unwrap()
+}
+
+#[tokio::test]
+async fn resumes_the_same_version() {
+    let dir = TempDir::new().unwrap();
+
+    let requests = resume(dir.path(), "01234", Some(ETAG)).await;
+    assert_eq!(requests.len(), 1);
+}
+
+#[tokio::test]
+async fn starts_over_when_the_file_changed() {
+    let dir = TempDir::new().unwrap();
+
+    // The server sends the whole new version instead of a range
+    let requests = resume(dir.path(), "abcde", Some("\"v1\"")).await;
+    assert_eq!(requests.len(), 1);
+}
+
+#[tokio::test]
+async fn starts_over_when_the_range_is_past_the_end() {
+    let dir = TempDir::new().unwrap();
+
+    let requests = resume(dir.path(), "0123456789ab", Some(ETAG)).await;
+    assert_eq!(requests.len(), 2);
+}
+
+#[tokio::test]
+async fn starts_over_without_knowing_the_version() {
+    let dir = TempDir::new().unwrap();
+
+    let requests = resume(dir.path(), "abcde", None).await;
+    assert_eq!(requests.len(), 1);
+    assert!(!requests[0].headers.contains_key(&HeaderName::from("range")));
+}
+
+/// Reads a request's head, and answers it with `response`, then hangs up.
+async fn answer(socket: &mut TcpStream, response: String) -> String {
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+
+    while !request.ends_with(b"\r\n\r\n") {
+        let read = socket.read(&mut buf).await.unwrap();
+        request.extend_from_slice(&buf[..read]);
+    }
+
+    socket.write_all(response.as_bytes()).await.unwrap();
+    socket.shutdown().await.unwrap();
+    String::from_utf8(request).unwrap().to_lowercase()
+}
+
+#[tokio::test]
+async fn resumes_when_the_connection_drops() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let host = format!("http://{}", listener.local_addr().unwrap());
+    let dir = TempDir::new().unwrap();
+
+    // Hangs up halfway through the file, then sends the rest
+    let server = tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let first = answer(
+            &mut socket,
+            format!(
+                "HTTP/1.1 200 OK\r\nETag: {}\r\nContent-Length: 10\r\n\r\n{}",
+                ETAG,
+                &FILE[..5]
+            ),
+        )
+        .await;
+
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let second = answer(
+            &mut socket,
+            format!(
+                "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes 5-9/10\r\n\
+                Content-Length: 5\r\nConnection: close\r\n\r\n{}",
+                &FILE[5..]
+            ),
+        )
+        .await;
+
+        (first, second)
+    });
+
+    let mut options = ClientOptions::new("key");
+    options.media_host = Some(host);
+    options.retry.base_delay = Duration::from_millis(1);
+
+    let download = DownloadOptions {
+        directory: dir.path().to_string_lossy().to_string(),
+        ..DownloadOptions::default()
+    };
+    let manifest = Manifest::load(&download.directory).unwrap();
+    let downloader = Downloader::new(&Client::new(options).unwrap(), download, manifest);
+
+    let post: Post = serde_json::from_value(post(2)).unwrap();
+    let bar = ProgressBar::hidden();
+    let pending = downloader.download_posts(vec![post], &bar);
+    let posts = downloader.finish(pending, &bar).await.unwrap();
+    assert!(posts[0].is_complete(), "{:?}", posts[0].failed);
+
+    assert_eq!(fs::read_to_string(dir.path().join(KEY)).unwrap(), FILE);
+
+    let (first, second) = server.await.unwrap();
+    assert!(!first.contains("range:"));
+    assert!(second.contains("range: bytes=5-"));
+    assert!(second.contains(&format!("if-range: {}", ETAG)));
+}
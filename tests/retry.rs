@@ -0,0 +1,101 @@
+//! Retrying requests that fail for a moment, with `RetryPolicy`.
+
+use std::time::{Duration, Instant, SystemTime};
+use tumblr_likes::retry::RetryPolicy;
+use wiremock::matchers::method;
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn policy(max_attempts: u32, base_delay: Duration) -> RetryPolicy {
+    RetryPolicy {
+        max_attempts,
+        base_delay,
+    }
+}
+
+/// Fails every request with `status`, `times` times, then succeeds.
+async fn failing(status: u16, times: u64, retry_after: Option<&str>) -> MockServer {
+    let server = MockServer::start().await;
+    let mut failure = ResponseTemplate::new(status);
+
+    if let Some(retry_after) = retry_after {
+        failure = failure.insert_header("Retry-After", retry_after);
+    }
+
+    Mock::given(method("GET"))
+        .respond_with(failure)
+        .up_to_n_times(times)
+        .with_priority(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&server)
+        .await;
+
+    server
+}
+
+async fn send(server: &MockServer, policy: &RetryPolicy) -> (u16, usize, Duration) {
+    let client = reqwest::Client::new();
+    let started = Instant::now();
+
+    let res = policy.send(|| client.get(server.uri())).await.unwrap();
+    let requests = server.received_requests().await.unwrap().len();
+
+    (res.status().as_u16(), requests, started.elapsed())
+}
+
+#[tokio::test]
+async fn retries_server_errors() {
+    let server = failing(503, 2, None).await;
+
+    let (status, requests, _) = send(&server, &policy(6, Duration::from_millis(1))).await;
+    assert_eq!((status, requests), (200, 3));
+}
+
+#[tokio::test]
+async fn retries_too_many_requests() {
+    let server = failing(429, 1, None).await;
+
+    let (status, requests, _) = send(&server, &policy(6, Duration::from_millis(1))).await;
+    assert_eq!((status, requests), (200, 2));
+}
+
+#[tokio::test]
+async fn leaves_other_errors_alone() {
+    let server = failing(404, 1, None).await;
+
+    let (status, requests, _) = send(&server, &policy(6, Duration::from_millis(1))).await;
+    assert_eq!((status, requests), (404, 1));
+}
+
+#[tokio::test]
+async fn backs_off_then_hands_back_the_last_failure() {
+    let server = failing(500, 10, None).await;
+
+    // Waits at least half of 100ms, then half of 200ms
+    let (status, requests, elapsed) = send(&server, &policy(3, Duration::from_millis(100))).await;
+    assert_eq!((status, requests), (500, 3));
+    assert!(elapsed >= Duration::from_millis(150), "{:?}", elapsed);
+    assert!(elapsed < Duration::from_millis(1000), "{:?}", elapsed);
+}
+
+#[tokio::test]
+async fn waits_the_seconds_in_retry_after() {
+    let server = failing(429, 1, Some("1")).await;
+
+    let (status, _, elapsed) = send(&server, &policy(2, Duration::from_millis(1))).await;
+    assert_eq!(status, 200);
+    assert!(elapsed >= Duration::from_secs(1), "{:?}", elapsed);
+}
+
+#[tokio::test]
+async fn waits_until_the_date_in_retry_after() {
+    let until = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(2));
+    let server = failing(503, 1, Some(&until)).await;
+
+    // Dates only have whole seconds, so this is at least one
+    let (status, _, elapsed) = send(&server, &policy(2, Duration::from_millis(1))).await;
+    assert_eq!(status, 200);
+    assert!(elapsed >= Duration::from_secs(1), "{:?}", elapsed);
+}
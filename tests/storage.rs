@@ -0,0 +1,277 @@
+//! Storing downloads locally, and in a stand-in for an S3 compatible server.
+
+mod common;
+
+use common::{client, tumblr, BLOG, POSTS};
+use futures::TryStreamExt;
+use indicatif::ProgressBar;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+use tumblr_likes::{
+    Client, DownloadOptions, Downloader, Error, Likes, LikesSource, LocalStorage, Manifest, Post,
+    S3Options, S3Storage, Storage,
+};
+use wiremock::http::{HeaderName, Method};
+use wiremock::matchers::path_regex;
+use wiremock::{Mock, MockServer, Request, Respond, ResponseTemplate};
+
+const BUCKET: &str = "archive";
+
+/// Keeps objects in memory like a bucket would, by path. Only signed requests
+/// whose body matches its declared hash are let through, and uploads with an
+/// unsigned body must say how long it is.
+#[derive(Clone, Default)]
+struct Bucket {
+    objects: Arc<Mutex<HashMap<String, Vec<u8>>>>,
+}
+
+impl Respond for Bucket {
+    fn respond(&self, req: &Request) -> ResponseTemplate {
+        // Values are split at commas, so put them back together
+        let header = |name: &str| match req.headers.get(&HeaderName::from(name)) {
+            Some(values) => values
+                .iter()
+                .map(|v| v.as_str())
+                .collect::<Vec<_>>()
+                .join(","),
+            None => String::new(),
+        };
+
+        let payload = match header("x-amz-content-sha256").as_str() {
+            "UNSIGNED-PAYLOAD" => header("content-length") == req.body.len().to_string(),
+            hash => hash == format!("{:x}", Sha256::digest(&req.body)),
+        };
+        let signed =
+            header("authorization").starts_with("AWS4-HMAC-SHA256 Credential=minio/") && payload;
+
+        if !signed {
+            return ResponseTemplate::new(403);
+        }
+
+        let mut objects = self.objects.lock().unwrap();
+        let key = req.url.path().to_string();
+
+        match req.method {
+            Method::Put => {
+                objects.insert(key, req.body.clone());
+                ResponseTemplate::new(200)
+            }
+            Method::Head => match objects.get(&key) {
+                Some(body) => ResponseTemplate::new(200)
+                    .insert_header("Content-Length", body.len().to_string().as_str()),
+                None => ResponseTemplate::new(404),
+            },
+            Method::Delete => {
+                objects.remove(&key);
+                ResponseTemplate::new(204)
+            }
+            _ => ResponseTemplate::new(405),
+        }
+    }
+}
+
+async fn minio() -> (MockServer, Bucket) {
+    let server = MockServer::start().await;
+    let bucket = Bucket::default();
+
+    Mock::given(path_regex(format!("^/{}/", BUCKET)))
+        .respond_with(bucket.clone())
+        .mount(&server)
+        .await;
+
+    (server, bucket)
+}
+
+fn s3(client: &Client, server: &MockServer) -> S3Storage {
+    S3Storage::new(
+        client,
+        S3Options {
+            endpoint: server.uri(),
+            bucket: BUCKET.to_string(),
+            region: "us-east-1".to_string(),
+            prefix: "likes/".to_string(),
+            access_key: "minio".to_string(),
+            secret_key: "minio123".to_string(),
+        },
+    )
+    .unwrap()
+}
+
+#[tokio::test]
+async fn stores_files_locally() {
+    let dir = TempDir::new().unwrap();
+    let storage = LocalStorage::new(dir.path().join("out"));
+
+    let file = dir.path().join("file.part");
+    fs::write(&file, "contents").unwrap();
+
+    assert_eq!(storage.stat("pics/file.jpg").await.unwrap(), None);
+
+    storage.put("pics/file.jpg", &file).await.unwrap();
+
+    assert!(!file.exists());
+    assert_eq!(storage.stat("pics/file.jpg").await.unwrap(), Some(8));
+    assert!(storage.exists("pics/file.jpg").await.unwrap());
+    assert_eq!(
+        fs::read_to_string(dir.path().join("out/pics/file.jpg")).unwrap(),
+        "contents"
+    );
+
+    storage.remove("pics/file.jpg").await.unwrap();
+    assert_eq!(storage.stat("pics/file.jpg").await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn verifying_replaces_incomplete_files() {
+    let tumblr = tumblr().await;
+    let dir = TempDir::new().unwrap();
+    let client = client(&tumblr);
+
+    let options = DownloadOptions {
+        directory: dir.path().to_string_lossy().to_string(),
+        ..DownloadOptions::default()
+    };
+
+    let download = |manifest: Manifest| {
+        let downloader = Downloader::new(&client, options.clone(), manifest);
+        let source = LikesSource::new(&client, Likes::Blog(BLOG.to_string()));
+
+        async move {
+            let posts: Vec<Post> = source.posts().try_collect().await.unwrap();
+            let bar = ProgressBar::hidden();
+
+            let pending = downloader.download_posts(posts, &bar);
+            downloader.finish(pending, &bar).await.unwrap();
+        }
+    };
+
+    download(Manifest::load(&options.directory).unwrap()).await;
+
+    let file = dir.path().join("pics/1600000002_2_0.jpg");
+    fs::write(&file, "TRUNC").unwrap();
+
+    let mut manifest = Manifest::load(&options.directory).unwrap();
+    let broken = manifest
+        .verify(&LocalStorage::new(dir.path()))
+        .await
+        .unwrap();
+
+    assert_eq!(broken.len(), 1);
+    assert!(!file.exists());
+
+    download(manifest).await;
+
+    let manifest = Manifest::load(&options.directory).unwrap();
+    let url = "https://64.media.tumblr.com/abc/p2_1280.jpg";
+
+    assert_eq!(fs::read_to_string(&file).unwrap(), "/abc/p2_1280.jpg");
+    assert_eq!(manifest.archived(2, url).unwrap().size, 16);
+}
+
+#[tokio::test]
+async fn stores_files_in_s3() {
+    let (server, bucket) = minio().await;
+    let storage = s3(&client(&server), &server);
+    let dir = TempDir::new().unwrap();
+
+    let file = dir.path().join("file.part");
+    fs::write(&file, "contents").unwrap();
+
+    assert_eq!(storage.stat("pics/a file.jpg").await.unwrap(), None);
+
+    storage.put("pics/a file.jpg", &file).await.unwrap();
+
+    // Uploaded files don't stay behind
+    assert!(!file.exists());
+    assert_eq!(storage.stat("pics/a file.jpg").await.unwrap(), Some(8));
+    assert!(storage.exists("pics/a file.jpg").await.unwrap());
+
+    {
+        let objects = bucket.objects.lock().unwrap();
+        assert_eq!(
+            objects.get("/archive/likes/pics/a%20file.jpg").unwrap(),
+            b"contents"
+        );
+    }
+
+    storage.remove("pics/a file.jpg").await.unwrap();
+    assert_eq!(storage.stat("pics/a file.jpg").await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn rejects_an_invalid_endpoint() {
+    let server = tumblr().await;
+    let options = S3Options {
+        endpoint: "minio:9000".to_string(),
+        bucket: BUCKET.to_string(),
+        region: "us-east-1".to_string(),
+        prefix: String::new(),
+        access_key: "minio".to_string(),
+        secret_key: "minio123".to_string(),
+    };
+
+    assert!(matches!(
+        S3Storage::new(&client(&server), options),
+        Err(Error::Config(_))
+    ));
+}
+
+#[tokio::test]
+async fn downloads_into_s3() {
+    let tumblr = tumblr().await;
+    let (minio, bucket) = minio().await;
+    let dir = TempDir::new().unwrap();
+    let client = client(&tumblr);
+
+    let options = DownloadOptions {
+        directory: dir.path().to_string_lossy().to_string(),
+        ..DownloadOptions::default()
+    };
+
+    let download = |manifest: Manifest| {
+        let downloader = Downloader::new(&client, options.clone(), manifest)
+            .storing_in(Arc::new(s3(&client, &minio)));
+        let source = LikesSource::new(&client, Likes::Blog(BLOG.to_string()));
+
+        async move {
+            let posts: Vec<Post> = source.posts().try_collect().await.unwrap();
+            let bar = ProgressBar::hidden();
+
+            let pending = downloader.download_posts(posts, &bar);
+            downloader.finish(pending, &bar).await.unwrap();
+        }
+    };
+
+    download(Manifest::load(&options.directory).unwrap()).await;
+
+    let objects = bucket.objects.lock().unwrap().clone();
+    assert_eq!(objects.len(), POSTS as usize);
+    assert_eq!(
+        objects[&format!("/{}/likes/pics/1600000002_2_0.jpg", BUCKET)],
+        b"/abc/p2_1280.jpg"
+    );
+
+    // Only the manifest is kept locally
+    let manifest = Manifest::load(&options.directory).unwrap();
+    assert_eq!(manifest.posts.len(), POSTS as usize);
+    assert!(!dir.path().join("pics/1600000002_2_0.jpg").exists());
+
+    // Everything is already in the bucket the second time around
+    let uploads = || async {
+        minio
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .filter(|r| r.method == Method::Put)
+            .count()
+    };
+    let before = uploads().await;
+
+    download(manifest).await;
+
+    assert_eq!(uploads().await, before);
+}